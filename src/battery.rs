@@ -0,0 +1,76 @@
+use crate::hardware::mapper::Mapper;
+use std::{
+    fs,
+    io::{ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+/// Battery-backed PRG RAM or a writable disk, persisted to a `.sav` file next to the ROM
+pub struct Battery {
+    path: PathBuf,
+    saved: Vec<u8>,
+}
+
+impl Battery {
+    pub fn new(rom_path: &Path) -> Self {
+        Self {
+            path: rom_path.with_extension("sav"),
+            saved: Vec::new(),
+        }
+    }
+
+    /// Restores the save file into the mapper. A missing save file leaves the mapper as it is
+    pub fn load(&mut self, mapper: &mut dyn Mapper) -> Result<()> {
+        match fs::read(&self.path) {
+            Ok(content) => mapper.load_save_data(&content),
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        };
+        self.saved = mapper.save_data().unwrap_or_default();
+        Ok(())
+    }
+
+    /// Writes the save data of the mapper to the save file, unless it is unchanged since the last load or flush
+    pub fn flush(&mut self, mapper: &dyn Mapper) -> Result<()> {
+        match mapper.save_data() {
+            Some(save_data) if save_data != self.saved => {
+                fs::write(&self.path, &save_data)?;
+                self.saved = save_data;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Battery;
+    use crate::{
+        hardware::mapper::{Mapper, NROM},
+        rom::{Mirroring, PRG_RAM_PAGE_SIZE},
+    };
+    use std::{env, fs};
+
+    #[test]
+    pub fn test_battery() {
+        let rom_path = env::temp_dir().join(format!("dam4nes_test_battery_{}.nes", std::process::id()));
+        let save_path = rom_path.with_extension("sav");
+        let _ = fs::remove_file(&save_path);
+
+        let mut mapper = NROM::new(vec![], vec![], 0, PRG_RAM_PAGE_SIZE, Mirroring::Horizontal);
+        let mut battery = Battery::new(&rom_path);
+        battery.load(&mut mapper).unwrap();
+        battery.flush(&mapper).unwrap();
+        assert!(!save_path.exists());
+
+        mapper.cpu_write(0x6010, 0xAA);
+        battery.flush(&mapper).unwrap();
+        assert_eq!(fs::read(&save_path).unwrap().len(), PRG_RAM_PAGE_SIZE);
+
+        let mut mapper = NROM::new(vec![], vec![], 0, PRG_RAM_PAGE_SIZE, Mirroring::Horizontal);
+        Battery::new(&rom_path).load(&mut mapper).unwrap();
+        assert_eq!(mapper.cpu_read(0x6010), Some(0xAA));
+        fs::remove_file(&save_path).unwrap();
+    }
+}
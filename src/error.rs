@@ -19,3 +19,156 @@ impl Display for InvalidOpCode {
 }
 
 impl Error for InvalidOpCode {}
+
+/// Reasons a file can't be loaded as a cartridge
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ROMError {
+    InvalidMagic,
+    TruncatedHeader,
+    /// Bytes 11-15 of an iNES 1.0 header aren't zero, typically because a ripping tool wrote its name there
+    DirtyHeader,
+    TruncatedTrainer,
+    /// The board has no PRG RAM at $6000-$7FFF to copy the trainer to
+    UnsupportedTrainer(u16),
+    InvalidPRGROMSize,
+    InvalidCHRROMSize,
+    TruncatedPRGROM {
+        expected: usize,
+        actual: usize,
+    },
+    TruncatedCHRROM {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedMapper(u16),
+    /// FDS images are made of 65500 byte disk sides
+    InvalidDiskImage,
+    /// The FDS BIOS is an 8 KiB ROM
+    InvalidBIOS,
+    TruncatedChunk(String),
+    InvalidChunk(String),
+    MissingChunk(&'static str),
+    /// A UNIF PCKn or CCKn chunk doesn't match the CRC-32 of its PRGn or CHRn chunk
+    ChunkChecksumMismatch(String),
+    UnsupportedBoard(String),
+}
+
+impl Display for ROMError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ROMError::InvalidMagic => write!(f, "Input is not a valid iNES file format"),
+            ROMError::TruncatedHeader => write!(f, "iNES header is truncated"),
+            ROMError::DirtyHeader => write!(f, "Unused bytes of the iNES header aren't zero"),
+            ROMError::TruncatedTrainer => write!(f, "Trainer is truncated"),
+            ROMError::UnsupportedTrainer(mapper_number) => {
+                write!(f, "Mapper {} has no PRG RAM to load the trainer into", mapper_number)
+            }
+            ROMError::InvalidPRGROMSize => write!(f, "NES 2.0 PRG ROM size is too large"),
+            ROMError::InvalidCHRROMSize => write!(f, "NES 2.0 CHR ROM size is too large"),
+            ROMError::TruncatedPRGROM { expected, actual } => {
+                write!(f, "PRG ROM is truncated. Expected {} bytes, found {}", expected, actual)
+            }
+            ROMError::TruncatedCHRROM { expected, actual } => {
+                write!(f, "CHR ROM is truncated. Expected {} bytes, found {}", expected, actual)
+            }
+            ROMError::UnsupportedMapper(mapper_number) => write!(f, "Unsupported mapper {}", mapper_number),
+            ROMError::InvalidDiskImage => write!(f, "FDS image size isn't a multiple of the disk side size"),
+            ROMError::InvalidBIOS => write!(f, "FDS BIOS isn't 8 KiB"),
+            ROMError::TruncatedChunk(id) => write!(f, "Chunk {} is truncated", id),
+            ROMError::InvalidChunk(id) => write!(f, "Invalid or unsupported chunk {}", id),
+            ROMError::MissingChunk(id) => write!(f, "File has no {} chunk", id),
+            ROMError::ChunkChecksumMismatch(id) => write!(f, "UNIF checksum {} doesn't match its chunk", id),
+            ROMError::UnsupportedBoard(board) => write!(f, "Unsupported UNIF board {}", board),
+        }
+    }
+}
+
+impl Error for ROMError {}
+
+/// Reasons an IPS, UPS or BPS patch can't be applied
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    /// The patch reads or writes outside of the source or the target
+    OutOfBounds,
+    SourceSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The target size read from a UPS or BPS patch is beyond what any ROM needs
+    TargetTooLarge(usize),
+    TargetSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    SourceChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    TargetChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    PatchChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Patch is not in IPS, UPS or BPS format"),
+            PatchError::Truncated => write!(f, "Patch is truncated"),
+            PatchError::OutOfBounds => write!(f, "Patch accesses data out of bounds"),
+            PatchError::SourceSizeMismatch { expected, actual } => {
+                write!(f, "Patch expects a {} byte ROM, found {} bytes", expected, actual)
+            }
+            PatchError::TargetTooLarge(size) => write!(f, "Patch target of {} bytes is too large", size),
+            PatchError::TargetSizeMismatch { expected, actual } => {
+                write!(f, "Patched ROM should be {} bytes, found {} bytes", expected, actual)
+            }
+            PatchError::SourceChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Patch expects a ROM with CRC-32 {:08X}, found {:08X}",
+                    expected, actual
+                )
+            }
+            PatchError::TargetChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Patched ROM should have CRC-32 {:08X}, found {:08X}",
+                    expected, actual
+                )
+            }
+            PatchError::PatchChecksumMismatch { expected, actual } => {
+                write!(f, "Patch should have CRC-32 {:08X}, found {:08X}", expected, actual)
+            }
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Reasons a ROM can't be unpacked from a zip or gzip container
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArchiveError {
+    Corrupted(String),
+    /// The zip archive has no entry with a ROM extension
+    NoROM,
+    EntryNotFound(String),
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ArchiveError::Corrupted(message) => write!(f, "Archive is corrupted. {}", message),
+            ArchiveError::NoROM => write!(f, "Archive contains no ROM"),
+            ArchiveError::EntryNotFound(name) => write!(f, "Archive has no entry named {}", name),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
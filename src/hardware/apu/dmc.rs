@@ -0,0 +1,224 @@
+/// Timer periods in CPU cycles, selected by the lower 4 bits of $4010
+pub const NTSC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+const IRQ_ENABLED_VALUE: u8 = 0b1000_0000;
+const LOOP_VALUE: u8 = 0b0100_0000;
+
+/// Delta modulation channel at $4010-$4013. It plays 1-bit delta encoded samples that it fetches from
+/// $8000-$FFFF by DMA, and its 7-bit output level can also be set directly through $4011
+pub struct DMC {
+    rates: &'static [u16; 16],
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    /// Byte fetched by the memory reader, waiting for the output unit
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    interrupt: bool,
+}
+
+impl DMC {
+    pub fn new(rates: &'static [u16; 16]) -> Self {
+        Self {
+            rates,
+            irq_enabled: false,
+            looping: false,
+            timer_period: rates[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            interrupt: false,
+        }
+    }
+
+    /// `register` is the offset of the register from $4010
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = (value & IRQ_ENABLED_VALUE) != 0;
+                if !self.irq_enabled {
+                    self.interrupt = false;
+                }
+                self.looping = (value & LOOP_VALUE) != 0;
+                self.timer_period = self.rates[(value & 0x0F) as usize];
+            }
+            1 => self.output_level = value & 0x7F,
+            2 => self.sample_address = 0xC000 + value as u16 * 64,
+            _ => self.sample_length = value as u16 * 16 + 1,
+        }
+    }
+
+    /// Enabling the channel through $4015 starts the sample unless it is still playing, and disabling it stops the
+    /// sample once the buffered byte is played. Either way acknowledges the interrupt
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.interrupt = false;
+        match enabled {
+            false => self.bytes_remaining = 0,
+            true if self.bytes_remaining == 0 => self.restart(),
+            true => (),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+
+    /// Address the memory reader wants to fetch, once the sample buffer is empty and bytes of the sample remain
+    pub fn dma_address(&self) -> Option<u16> {
+        match self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            true => Some(self.current_address),
+            false => None,
+        }
+    }
+
+    /// Fills the sample buffer with the byte fetched from `dma_address`
+    pub fn load_sample(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        // The address wraps around to $8000 instead of $0000
+        self.current_address = match self.current_address {
+            0xFFFF => 0x8000,
+            address => address + 1,
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Called every CPU cycle
+    pub fn step_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period - 1;
+                self.clock_output();
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Moves the output level up or down by 2 for each bit of the shift register, staying within 0-127
+    fn clock_output(&mut self) {
+        if !self.silence {
+            match self.shift_register & 0x01 {
+                0 if self.output_level >= 2 => self.output_level -= 2,
+                1 if self.output_level <= 125 => self.output_level += 2,
+                _ => (),
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.shift_register = sample;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{DMC, NTSC_RATES};
+
+    #[test]
+    pub fn test_memory_reader() {
+        let mut dmc = DMC::new(&NTSC_RATES);
+        dmc.write_register(0, 0x80);
+        dmc.write_register(2, 0xFF);
+        dmc.write_register(3, 0x01);
+        assert_eq!(dmc.dma_address(), None);
+
+        dmc.set_enabled(true);
+        assert!(dmc.is_active());
+        for offset in 0..17 {
+            assert_eq!(dmc.dma_address(), Some(0xFFC0 + offset));
+            dmc.load_sample(0x00);
+            assert_eq!(dmc.dma_address(), None);
+            dmc.sample_buffer = None;
+        }
+        assert!(!dmc.is_active());
+        assert!(dmc.interrupt());
+        dmc.set_enabled(false);
+        assert!(!dmc.interrupt());
+
+        // A looping sample restarts instead of raising the interrupt
+        dmc.write_register(0, 0xC0);
+        dmc.write_register(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.load_sample(0x00);
+        assert_eq!(dmc.dma_address(), None);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.dma_address(), Some(0xFFC0));
+        assert!(!dmc.interrupt());
+    }
+
+    #[test]
+    pub fn test_output_unit() {
+        let mut dmc = DMC::new(&NTSC_RATES);
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 0x40);
+        assert_eq!(dmc.output(), 0x40);
+
+        dmc.write_register(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.load_sample(0b0000_0111);
+        // The output unit finishes the 8 silent bits it started with before taking the buffered byte
+        for _ in 0..8 * NTSC_RATES[0xF] {
+            dmc.step_timer();
+        }
+        assert_eq!(dmc.output(), 0x40);
+        for _ in 0..8 * NTSC_RATES[0xF] {
+            dmc.step_timer();
+        }
+        assert_eq!(dmc.output(), 0x40 + 3 * 2 - 5 * 2);
+
+        dmc.write_register(1, 0x7F);
+        dmc.set_enabled(true);
+        dmc.load_sample(0xFF);
+        for _ in 0..16 * NTSC_RATES[0xF] {
+            dmc.step_timer();
+        }
+        assert_eq!(dmc.output(), 0x7F);
+    }
+}
@@ -0,0 +1,463 @@
+mod dmc;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::rom::Timing;
+use dmc::DMC;
+use noise::Noise;
+use pulse::{Pulse, SweepNegation};
+use std::{f32::consts::PI, mem};
+use triangle::Triangle;
+
+pub const NTSC_CPU_CLOCK: u32 = 1_789_773;
+pub const PAL_CPU_CLOCK: u32 = 1_662_607;
+/// The console's output stage blocks DC with a high-pass filter at about 90 Hz
+const HIGH_PASS_CUTOFF: f32 = 90.0;
+
+const STATUS_PULSE_1_VALUE: u8 = 0b0000_0001;
+const STATUS_PULSE_2_VALUE: u8 = 0b0000_0010;
+const STATUS_TRIANGLE_VALUE: u8 = 0b0000_0100;
+const STATUS_NOISE_VALUE: u8 = 0b0000_1000;
+const STATUS_DMC_VALUE: u8 = 0b0001_0000;
+const STATUS_FRAME_INTERRUPT_VALUE: u8 = 0b0100_0000;
+const STATUS_DMC_INTERRUPT_VALUE: u8 = 0b1000_0000;
+const FRAME_COUNTER_MODE_VALUE: u8 = 0b1000_0000;
+const FRAME_COUNTER_IRQ_INHIBIT_VALUE: u8 = 0b0100_0000;
+
+/// Values loaded into the length counters by the upper 5 bits of $4003, $4007, $400B and $400F
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16,
+    28, 32, 30,
+];
+
+/// CPU cycles of the frame counter steps. The 4-step sequence ends on the 4th value and the 5-step one on the 5th
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// The 2A03 audio processing unit, with two pulse channels, a triangle, a noise and a delta modulation channel
+/// mixed into samples at the output rate. $4000-$4013, $4015 and $4017 are its registers
+pub struct APU {
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: DMC,
+    frame_steps: [u32; 5],
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_interrupt: bool,
+    /// CPU cycles left until a write to $4017 restarts the frame counter
+    frame_reset_delay: Option<u8>,
+    /// Number of CPU cycles elapsed since power up
+    cycles: u64,
+    cycles_per_sample: f32,
+    sample_cycles: f32,
+    sample_sum: f32,
+    sample_count: u32,
+    high_pass_factor: f32,
+    high_pass_input: f32,
+    high_pass_output: f32,
+    samples: Vec<f32>,
+}
+
+impl APU {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::with_sample_rate(Timing::NTSC, 44_100)
+    }
+
+    /// `sample_rate` is the number of samples per second that `take_samples` returns
+    pub fn with_sample_rate(timing: Timing, sample_rate: u32) -> Self {
+        let (cpu_clock, frame_steps, noise_periods, dmc_rates) = match timing {
+            Timing::PAL => (PAL_CPU_CLOCK, PAL_FRAME_STEPS, &noise::PAL_PERIODS, &dmc::PAL_RATES),
+            _ => (NTSC_CPU_CLOCK, NTSC_FRAME_STEPS, &noise::NTSC_PERIODS, &dmc::NTSC_RATES),
+        };
+        let rc = 1.0 / (2.0 * PI * HIGH_PASS_CUTOFF);
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            pulse_1: Pulse::new(SweepNegation::OnesComplement),
+            pulse_2: Pulse::new(SweepNegation::TwosComplement),
+            triangle: Triangle::new(),
+            noise: Noise::new(noise_periods),
+            dmc: DMC::new(dmc_rates),
+            frame_steps,
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_interrupt: false,
+            frame_reset_delay: None,
+            cycles: 0,
+            cycles_per_sample: cpu_clock as f32 / sample_rate as f32,
+            sample_cycles: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            high_pass_factor: rc / (rc + dt),
+            high_pass_input: 0.0,
+            high_pass_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Reading $4015 tells which channels are playing and which interrupts are pending. It acknowledges the frame
+    /// interrupt, but not the DMC one
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulse_1.is_active() {
+            status |= STATUS_PULSE_1_VALUE;
+        }
+        if self.pulse_2.is_active() {
+            status |= STATUS_PULSE_2_VALUE;
+        }
+        if self.triangle.is_active() {
+            status |= STATUS_TRIANGLE_VALUE;
+        }
+        if self.noise.is_active() {
+            status |= STATUS_NOISE_VALUE;
+        }
+        if self.dmc.is_active() {
+            status |= STATUS_DMC_VALUE;
+        }
+        if self.frame_interrupt {
+            status |= STATUS_FRAME_INTERRUPT_VALUE;
+        }
+        if self.dmc.interrupt() {
+            status |= STATUS_DMC_INTERRUPT_VALUE;
+        }
+        self.frame_interrupt = false;
+        status
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse_1.write_register(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse_2.write_register(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write_register(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write_register(address - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write_register(address - 0x4010, value),
+            0x4015 => {
+                self.pulse_1.set_enabled((value & STATUS_PULSE_1_VALUE) != 0);
+                self.pulse_2.set_enabled((value & STATUS_PULSE_2_VALUE) != 0);
+                self.triangle.set_enabled((value & STATUS_TRIANGLE_VALUE) != 0);
+                self.noise.set_enabled((value & STATUS_NOISE_VALUE) != 0);
+                self.dmc.set_enabled((value & STATUS_DMC_VALUE) != 0);
+            }
+            0x4017 => {
+                self.five_step_mode = (value & FRAME_COUNTER_MODE_VALUE) != 0;
+                self.irq_inhibit = (value & FRAME_COUNTER_IRQ_INHIBIT_VALUE) != 0;
+                if self.irq_inhibit {
+                    self.frame_interrupt = false;
+                }
+                // The restart waits for the next APU cycle, which is every other CPU cycle
+                self.frame_reset_delay = Some(match self.cycles % 2 {
+                    0 => 3,
+                    _ => 4,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    /// Level of the frame and DMC interrupt lines, which are wired to the CPU's IRQ
+    pub fn irq(&self) -> bool {
+        self.frame_interrupt || self.dmc.interrupt()
+    }
+
+    /// Address of the sample byte the DMC wants to fetch. The CPU is halted while the byte is read, and the byte is
+    /// handed over with `load_dmc_sample`
+    pub fn dmc_dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    pub fn load_dmc_sample(&mut self, value: u8) {
+        self.dmc.load_sample(value);
+    }
+
+    /// Advances the APU by one CPU cycle
+    pub fn step(&mut self) {
+        self.cycles += 1;
+        self.step_frame_counter();
+        self.pulse_1.step_timer();
+        self.pulse_2.step_timer();
+        self.triangle.step_timer();
+        self.noise.step_timer();
+        self.dmc.step_timer();
+
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_cycles += 1.0;
+        if self.sample_cycles >= self.cycles_per_sample {
+            self.sample_cycles -= self.cycles_per_sample;
+            let sample = self.sample_sum / self.sample_count as f32;
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+            self.high_pass_output = self.high_pass_factor * (self.high_pass_output + sample - self.high_pass_input);
+            self.high_pass_input = sample;
+            self.samples.push(self.high_pass_output);
+        }
+    }
+
+    /// Samples produced since the last call, averaged over the CPU cycles each of them spans
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        mem::take(&mut self.samples)
+    }
+
+    fn step_frame_counter(&mut self) {
+        if let Some(delay) = self.frame_reset_delay {
+            match delay {
+                0 => {
+                    self.frame_reset_delay = None;
+                    self.frame_cycle = 0;
+                    // The 5-step mode clocks the units right away
+                    if self.five_step_mode {
+                        self.clock_quarter_frame();
+                        self.clock_half_frame();
+                    }
+                }
+                delay => self.frame_reset_delay = Some(delay - 1),
+            }
+        }
+
+        self.frame_cycle += 1;
+        let [first, second, third, fourth, fifth] = self.frame_steps;
+        match (self.frame_cycle, self.five_step_mode) {
+            (cycle, _) if cycle == first || cycle == third => self.clock_quarter_frame(),
+            (cycle, _) if cycle == second => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            // The 4-step sequence raises the interrupt during its last 3 cycles
+            (cycle, false) if cycle == fourth - 1 => self.set_frame_interrupt(),
+            (cycle, false) if cycle == fourth => {
+                self.set_frame_interrupt();
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (cycle, false) if cycle == fourth + 1 => {
+                self.set_frame_interrupt();
+                self.frame_cycle = 0;
+            }
+            (cycle, true) if cycle == fifth => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (cycle, true) if cycle == fifth + 1 => self.frame_cycle = 0,
+            _ => (),
+        }
+    }
+
+    fn set_frame_interrupt(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_interrupt = true;
+        }
+    }
+
+    /// Clocks the envelopes and the linear counter
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.clock_quarter_frame();
+        self.pulse_2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Clocks the length counters and the sweep units
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.clock_half_frame();
+        self.pulse_2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// The nonlinear DAC of the 2A03, with an output between 0 and 1
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse_1.output() + self.pulse_2.output()) as f32;
+        let pulse_out = match pulse == 0.0 {
+            true => 0.0,
+            false => 95.88 / (8128.0 / pulse + 100.0),
+        };
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = match tnd == 0.0 {
+            true => 0.0,
+            false => 159.79 / (1.0 / tnd + 100.0),
+        };
+        pulse_out + tnd_out
+    }
+}
+
+/// Volume of the pulse and noise channels, either constant or decaying from 15 to 0
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    /// Constant volume, or the period of the decay divider
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Takes the lower 6 bits of $4000, $4004 or $400C
+    fn write(&mut self, value: u8) {
+        self.looping = (value & 0x20) != 0;
+        self.constant_volume = (value & 0x10) != 0;
+        self.volume = value & 0x0F;
+    }
+
+    fn restart(&mut self) {
+        self.start = true;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        match self.constant_volume {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
+
+/// Silences its channel once it counts down to 0, unless halted
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Disabling the channel through $4015 clears the counter
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    /// Loads the counter from the table entry in the upper 5 bits of `value`, if the channel is enabled
+    fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(value >> 3) as usize];
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Envelope, APU, NTSC_FRAME_STEPS};
+
+    #[test]
+    pub fn test_frame_interrupt() {
+        let mut apu = APU::new();
+        for _ in 0..NTSC_FRAME_STEPS[3] - 2 {
+            apu.step();
+        }
+        assert!(!apu.irq());
+        apu.step();
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x00);
+
+        apu.write_register(0x4017, 0x40);
+        for _ in 0..NTSC_FRAME_STEPS[4] {
+            apu.step();
+        }
+        assert!(!apu.irq());
+
+        // The 5-step sequence never raises the interrupt
+        apu.write_register(0x4017, 0x80);
+        for _ in 0..NTSC_FRAME_STEPS[4] * 2 {
+            apu.step();
+        }
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    pub fn test_length_counter() {
+        let mut apu = APU::new();
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status(), 0x00);
+
+        apu.write_register(0x4015, 0x0F);
+        // Loads 254 into the pulse 1 length counter, and 10 into the noise one
+        apu.write_register(0x4003, 0x08);
+        apu.write_register(0x400F, 0x00);
+        assert_eq!(apu.read_status(), 0x09);
+
+        // Each 4-step sequence clocks the length counters twice
+        apu.write_register(0x4017, 0x40);
+        for _ in 0..NTSC_FRAME_STEPS[3] * 5 + 10 {
+            apu.step();
+        }
+        assert_eq!(apu.read_status(), 0x01);
+
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    pub fn test_envelope() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x01);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 14);
+
+        envelope.write(0x15);
+        assert_eq!(envelope.output(), 5);
+    }
+
+    #[test]
+    pub fn test_samples() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0xBF);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0x08);
+        for _ in 0..29830 {
+            apu.step();
+        }
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), 735);
+        assert!(samples.iter().any(|sample| *sample > 0.01));
+        assert!(samples.iter().any(|sample| *sample < -0.01));
+        assert!(apu.take_samples().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Envelope, LengthCounter};
+
+/// Timer periods in CPU cycles, selected by the lower 4 bits of $400E
+pub const NTSC_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+/// Pseudo-random noise channel at $400C-$400F, clocking a 15-bit linear feedback shift register
+pub struct Noise {
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    /// Takes the feedback from bit 6 instead of bit 1, which gives a short, metallic sounding sequence
+    short_mode: bool,
+    shift_register: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new(periods: &'static [u16; 16]) -> Self {
+        Self {
+            periods,
+            timer_period: periods[0],
+            timer: 0,
+            short_mode: false,
+            shift_register: 0x0001,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// `register` is the offset of the register from $400C
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length_counter.set_halted((value & 0x20) != 0);
+                self.envelope.write(value);
+            }
+            1 => (),
+            2 => {
+                self.short_mode = (value & 0x80) != 0;
+                self.timer_period = self.periods[(value & 0x0F) as usize];
+            }
+            _ => {
+                self.length_counter.load(value);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Called every CPU cycle
+    pub fn step_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period - 1;
+                let tap = match self.short_mode {
+                    false => 1,
+                    true => 6,
+                };
+                let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x0001;
+                self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        match (self.shift_register & 0x0001) != 0 || !self.length_counter.is_active() {
+            true => 0,
+            false => self.envelope.output(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Noise, NTSC_PERIODS};
+
+    /// Steps the shift register `count` times and returns the number of different states it went through
+    fn period(short_mode: bool, count: usize) -> usize {
+        let mut noise = Noise::new(&NTSC_PERIODS);
+        noise.write_register(
+            2,
+            match short_mode {
+                false => 0x00,
+                true => 0x80,
+            },
+        );
+        let mut states = std::collections::HashSet::new();
+        for _ in 0..count * NTSC_PERIODS[0] as usize {
+            noise.step_timer();
+            states.insert(noise.shift_register);
+        }
+        states.len()
+    }
+
+    #[test]
+    pub fn test_shift_register() {
+        assert_eq!(period(false, 40000), 32767);
+        // The short sequence depends on the starting state, and is 93 steps long from the power up state
+        assert_eq!(period(true, 1000), 93);
+    }
+
+    #[test]
+    pub fn test_noise_output() {
+        let mut noise = Noise::new(&NTSC_PERIODS);
+        noise.set_enabled(true);
+        noise.write_register(0, 0x1A);
+        noise.write_register(3, 0x00);
+        assert_eq!(noise.output(), 0);
+        noise.step_timer();
+        assert_eq!(noise.output(), 10);
+        noise.set_enabled(false);
+        assert_eq!(noise.output(), 0);
+    }
+}
@@ -0,0 +1,193 @@
+use super::{Envelope, LengthCounter};
+
+/// Waveforms selected by the upper 2 bits of $4000 and $4004, stepped through backwards
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// How the sweep unit subtracts from the period. Pulse 1 adds the ones' complement of the change, so it sweeps
+/// one step lower than pulse 2
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SweepNegation {
+    OnesComplement,
+    TwosComplement,
+}
+
+/// Square wave channel, at $4000-$4003 for pulse 1 and $4004-$4007 for pulse 2
+pub struct Pulse {
+    negation: SweepNegation,
+    duty: usize,
+    sequence_position: usize,
+    /// 11-bit period of the timer, in APU cycles
+    timer_period: u16,
+    /// CPU cycles left until the sequencer steps
+    timer: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    pub fn new(negation: SweepNegation) -> Self {
+        Self {
+            negation,
+            duty: 0,
+            sequence_position: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+        }
+    }
+
+    /// `register` is the offset of the register from the first one of the channel
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = (value >> 6) as usize;
+                self.length_counter.set_halted((value & 0x20) != 0);
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = (value & 0x80) != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = (value & 0x08) != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((value & 0x07) as u16) << 8);
+                self.length_counter.load(value);
+                self.sequence_position = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Called every CPU cycle. The timer counts APU cycles, which are 2 CPU cycles long
+    pub fn step_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period * 2 + 1;
+                self.sequence_position = (self.sequence_position + 7) % 8;
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift != 0 && !self.is_muted() {
+            self.timer_period = self.target_period();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        match DUTY_SEQUENCES[self.duty][self.sequence_position] == 0
+            || !self.length_counter.is_active()
+            || self.is_muted()
+        {
+            true => 0,
+            false => self.envelope.output(),
+        }
+    }
+
+    /// Period the sweep unit moves towards, which is computed continuously even when the sweep is disabled
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        match (self.sweep_negate, self.negation) {
+            (false, _) => self.timer_period + change,
+            (true, SweepNegation::OnesComplement) => self.timer_period.saturating_sub(change + 1),
+            (true, SweepNegation::TwosComplement) => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    /// Periods that are too short for the speaker, or sweep targets out of the 11-bit range, silence the channel
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x07FF
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Pulse, SweepNegation};
+
+    #[test]
+    pub fn test_sweep() {
+        let mut pulse_1 = Pulse::new(SweepNegation::OnesComplement);
+        let mut pulse_2 = Pulse::new(SweepNegation::TwosComplement);
+        for pulse in [&mut pulse_1, &mut pulse_2].iter_mut() {
+            pulse.set_enabled(true);
+            pulse.write_register(1, 0x89);
+            pulse.write_register(2, 0x00);
+            pulse.write_register(3, 0x01);
+            pulse.clock_half_frame();
+        }
+        assert_eq!(pulse_1.timer_period, 0x0080 - 1);
+        assert_eq!(pulse_2.timer_period, 0x0080);
+
+        let mut pulse = Pulse::new(SweepNegation::TwosComplement);
+        pulse.set_enabled(true);
+        pulse.write_register(0, 0xFF);
+        pulse.write_register(1, 0x08);
+        pulse.write_register(2, 0x00);
+        pulse.write_register(3, 0x06);
+        pulse.step_timer();
+        assert_eq!(pulse.output(), 15);
+        // Adding half of the period overflows 11 bits
+        pulse.write_register(1, 0x01);
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    pub fn test_duty_sequence() {
+        let mut pulse = Pulse::new(SweepNegation::OnesComplement);
+        pulse.set_enabled(true);
+        pulse.write_register(0, 0x3F);
+        pulse.write_register(2, 0x08);
+        pulse.write_register(3, 0x00);
+        let mut outputs = Vec::new();
+        for _ in 0..8 {
+            pulse.step_timer();
+            outputs.push(pulse.output());
+            for _ in 0..17 {
+                pulse.step_timer();
+            }
+        }
+        assert_eq!(outputs, [0, 0, 0, 0, 0, 0, 15, 0]);
+    }
+}
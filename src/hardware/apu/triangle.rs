@@ -0,0 +1,119 @@
+use super::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Triangle wave channel at $4008-$400B. It has no volume control, but a linear counter that stops it
+/// with a finer resolution than the length counter
+pub struct Triangle {
+    timer_period: u16,
+    timer: u16,
+    sequence_position: usize,
+    length_counter: LengthCounter,
+    /// Halts the length counter and keeps the linear counter reloading
+    control: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+}
+
+impl Triangle {
+    pub fn new() -> Self {
+        Self {
+            timer_period: 0,
+            timer: 0,
+            sequence_position: 0,
+            length_counter: LengthCounter::default(),
+            control: false,
+            linear_counter_period: 0,
+            linear_counter: 0,
+            linear_counter_reload: false,
+        }
+    }
+
+    /// `register` is the offset of the register from $4008
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = (value & 0x80) != 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_counter_period = value & 0x7F;
+            }
+            1 => (),
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((value & 0x07) as u16) << 8);
+                self.length_counter.load(value);
+                self.linear_counter_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Called every CPU cycle, which is the rate of the triangle's timer. The sequencer only steps while both
+    /// counters are running, so a silenced triangle holds its last level
+    pub fn step_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+                if self.linear_counter > 0 && self.length_counter.is_active() {
+                    self.sequence_position = (self.sequence_position + 1) % SEQUENCE.len();
+                }
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_position]
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Triangle;
+
+    #[test]
+    pub fn test_linear_counter() {
+        let mut triangle = Triangle::new();
+        triangle.set_enabled(true);
+        triangle.write_register(0, 0x02);
+        triangle.write_register(2, 0x00);
+        triangle.write_register(3, 0x08);
+        triangle.step_timer();
+        assert_eq!(triangle.output(), 15);
+
+        triangle.clock_quarter_frame();
+        triangle.step_timer();
+        assert_eq!(triangle.output(), 14);
+
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        triangle.step_timer();
+        assert_eq!(triangle.output(), 14);
+        assert!(triangle.is_active());
+    }
+}
@@ -1,15 +1,23 @@
-use super::{
-    memory::{Memory, MemoryMapper},
-    ppu::PPU,
-};
+use super::{apu::APU, mapper::Mapper, memory::Memory, ppu::PPU};
 use std::fmt::{Display, Formatter};
 
 const INTERNAL_MEMORY_SIZE: usize = 2048;
+pub const INTERRUPT_CYCLES: u32 = 7;
+pub const UNUSED_FLAG_VALUE: u8 = 0b0010_0000;
+const OAM_DMA_CYCLES: u32 = 513;
+/// A DMC fetch halts the CPU for 1 to 4 cycles depending on what it was doing, most often 4
+const DMC_DMA_CYCLES: u32 = 4;
 
 #[derive(Copy, Clone)]
 pub struct CPU {
     pub registers: Registers,
     pub internal_memory: [u8; INTERNAL_MEMORY_SIZE],
+    /// Number of cycles elapsed since power up
+    pub cycles: u64,
+    oam_dma_pending: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    irq_line: bool,
 }
 
 impl CPU {
@@ -18,6 +26,11 @@ impl CPU {
         Self {
             registers: Default::default(),
             internal_memory: [0; INTERNAL_MEMORY_SIZE],
+            cycles: 0,
+            oam_dma_pending: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
         }
     }
 
@@ -28,23 +41,86 @@ impl CPU {
                 x: 0x00,
                 y: 0x00,
                 p: 0x34,
-                s: 0xFD,
+                // The RESET sequence run at power up decrements this to $FD
+                s: 0x00,
                 pc: 0x0000,
             },
             internal_memory: [0; INTERNAL_MEMORY_SIZE],
+            cycles: 0,
+            oam_dma_pending: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+        }
+    }
+
+    /// NMI is edge triggered, so only a low to high transition of the line requests an interrupt
+    pub fn set_nmi_line(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = level;
+    }
+
+    /// IRQ is level triggered, it keeps firing as long as the line is held high and interrupts are enabled
+    pub fn set_irq_line(&mut self, level: bool) {
+        self.irq_line = level;
+    }
+
+    pub fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    /// Returns the cycles the CPU is halted for by an OAM DMA started during the last instruction.
+    /// The DMA waits for an extra alignment cycle when it starts on an odd CPU cycle
+    pub fn take_oam_dma_cycles(&mut self) -> u32 {
+        let cycles = match self.oam_dma_pending {
+            false => 0,
+            true => OAM_DMA_CYCLES + (self.cycles % 2) as u32,
+        };
+        self.oam_dma_pending = false;
+        self.cycles += cycles as u64;
+        cycles
+    }
+
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.nmi_pending {
+            Some(Interrupt::NMI)
+        } else if self.irq_line && !self.registers.flags().interrupt_disable {
+            Some(Interrupt::IRQ)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Interrupt {
+    NMI,
+    RESET,
+    IRQ,
+}
+
+impl Interrupt {
+    pub fn vector(&self) -> u16 {
+        match self {
+            Interrupt::NMI => 0xFFFA,
+            Interrupt::RESET => 0xFFFC,
+            Interrupt::IRQ => 0xFFFE,
         }
     }
 }
 
-pub struct MMU<'a, 'b> {
+pub struct MMU<'a> {
     cpu: &'a mut CPU,
     ppu: &'a mut PPU,
-    mapper: Option<&'a MemoryMapper<'b>>,
+    apu: &'a mut APU,
+    mapper: Option<&'a mut dyn Mapper>,
 }
 
-impl<'a, 'b> MMU<'a, 'b> {
-    pub fn new(cpu: &'a mut CPU, ppu: &'a mut PPU, mapper: Option<&'a MemoryMapper<'b>>) -> Self {
-        Self { cpu, ppu, mapper }
+impl<'a> MMU<'a> {
+    pub fn new(cpu: &'a mut CPU, ppu: &'a mut PPU, apu: &'a mut APU, mapper: Option<&'a mut dyn Mapper>) -> Self {
+        Self { cpu, ppu, apu, mapper }
     }
 
     pub fn cpu(&self) -> &CPU {
@@ -55,7 +131,7 @@ impl<'a, 'b> MMU<'a, 'b> {
         self.cpu
     }
 
-    pub fn read_by_mode(&self, addressing_mode: AddressingMode) -> Option<u8> {
+    pub fn read_by_mode(&mut self, addressing_mode: AddressingMode) -> Option<u8> {
         match addressing_mode {
             AddressingMode::Accumulator => Some(self.cpu.registers.a),
             AddressingMode::Immediate(value) => Some(value),
@@ -66,11 +142,14 @@ impl<'a, 'b> MMU<'a, 'b> {
     pub fn write_8_bit_value_by_mode(&mut self, addressing_mode: AddressingMode, value: u8) {
         match addressing_mode {
             AddressingMode::Accumulator => self.cpu.registers.a = value,
-            mode => self.write(self.address_by_mode(mode).expect("Invalid addressing mode"), value),
+            mode => {
+                let address = self.address_by_mode(mode).expect("Invalid addressing mode");
+                self.write(address, value);
+            }
         }
     }
 
-    pub fn address_by_mode(&self, addressing_mode: AddressingMode) -> Option<u16> {
+    pub fn address_by_mode(&mut self, addressing_mode: AddressingMode) -> Option<u16> {
         match addressing_mode {
             AddressingMode::ZeroPage(address) => Some(address as u16),
             AddressingMode::ZeroPageX(address) => Some(address.wrapping_add(self.cpu.registers.x) as u16),
@@ -87,52 +166,76 @@ impl<'a, 'b> MMU<'a, 'b> {
             _ => None,
         }
     }
+
+    pub fn page_crossed_by_mode(&mut self, addressing_mode: AddressingMode) -> bool {
+        let registers = self.cpu.registers;
+        match addressing_mode {
+            AddressingMode::AbsoluteX(address) => crosses_page(address, address.wrapping_add(registers.x as u16)),
+            AddressingMode::AbsoluteY(address) => crosses_page(address, address.wrapping_add(registers.y as u16)),
+            AddressingMode::IndirectIndexed(address) => self
+                .read_16_bit_value(address as u16)
+                .map(|value| crosses_page(value, value.wrapping_add(registers.y as u16)))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+pub(crate) fn crosses_page(from: u16, to: u16) -> bool {
+    (from & 0xFF00) != (to & 0xFF00)
+}
+
+impl<'a> MMU<'a> {
+    /// Copies the 256 bytes of CPU page $XX00 to OAM through OAMDATA, starting at OAMADDR
+    fn oam_dma(&mut self, page: u8) {
+        self.ppu.registers.oamdma = page;
+        let start_address = (page as u16) << 8;
+        for offset in 0x00..=0xFF {
+            let value = self.read(start_address + offset).unwrap_or(0);
+            self.ppu.write_register(0x2004, value, self.mapper.as_deref_mut());
+        }
+        self.cpu.oam_dma_pending = true;
+    }
+
+    /// Fetches the sample byte the DMC is waiting for, if any, and returns the cycles the CPU is halted for
+    pub fn dmc_dma(&mut self) -> u32 {
+        match self.apu.dmc_dma_address() {
+            Some(address) => {
+                let value = self.read(address).unwrap_or(0);
+                self.apu.load_dmc_sample(value);
+                self.cpu.cycles += DMC_DMA_CYCLES as u64;
+                DMC_DMA_CYCLES
+            }
+            None => 0,
+        }
+    }
 }
 
-impl<'a, 'b> Memory for MMU<'a, 'b> {
-    fn read(&self, address: u16) -> Option<u8> {
+impl<'a> Memory for MMU<'a> {
+    fn read(&mut self, address: u16) -> Option<u8> {
         match address {
             0x0000..=0x1FFF => Some(self.cpu.internal_memory[address as usize % INTERNAL_MEMORY_SIZE]),
-            0x2000..=0x3FFF => {
-                let registers = &self.ppu.registers;
-                Some(match address % 0x08 {
-                    0 => registers.ppuctrl,
-                    1 => registers.ppumask,
-                    2 => registers.ppustatus,
-                    3 => registers.oamaddr,
-                    4 => registers.oamdata,
-                    5 => registers.ppuscroll,
-                    6 => registers.ppuaddr,
-                    7 => registers.ppudata,
-                    _ => unreachable!(),
-                })
-            }
+            0x2000..=0x3FFF => Some(self.ppu.read_register(address, self.mapper.as_deref_mut())),
+            0x4015 => Some(self.apu.read_status()),
             0x4000..=0x4017 => None,
             0x4018..=0x401F => None,
-            0x4020..=0xFFFF => self.mapper.and_then(|mapper| mapper.read(address)),
+            0x4020..=0xFFFF => self.mapper.as_deref_mut().and_then(|mapper| mapper.cpu_read(address)),
         }
     }
 
     fn write(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=0x1FFF => self.cpu.internal_memory[address as usize % INTERNAL_MEMORY_SIZE] = value,
-            0x2000..=0x3FFF => {
-                let registers = &mut self.ppu.registers;
-                match address % 0x08 {
-                    0 => registers.ppuctrl = value,
-                    1 => registers.ppumask = value,
-                    2 => registers.ppustatus = value,
-                    3 => registers.oamaddr = value,
-                    4 => registers.oamdata = value,
-                    5 => registers.ppuscroll = value,
-                    6 => registers.ppuaddr = value,
-                    7 => registers.ppudata = value,
-                    _ => unreachable!(),
+            0x2000..=0x3FFF => self.ppu.write_register(address, value, self.mapper.as_deref_mut()),
+            0x4014 => self.oam_dma(value),
+            0x4016 => (),
+            0x4000..=0x4017 => self.apu.write_register(address, value),
+            0x4018..=0x401F => (),
+            0x4020..=0xFFFF => {
+                if let Some(mapper) = self.mapper.as_deref_mut() {
+                    mapper.cpu_write(address, value);
                 }
             }
-            0x4000..=0x4017 => (),
-            0x4018..=0x401F => (),
-            _ => panic!("Access violation. Trying to write to read only address {:#X}", address),
         }
     }
 }
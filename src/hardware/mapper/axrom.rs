@@ -0,0 +1,96 @@
+use super::{bus_conflict, Banks, Mapper, CHR};
+use crate::rom::{Mirroring, CRH_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 32 * 1024;
+
+/// Mapper 7. A switchable 32 KiB PRG bank and a register selected single screen name table
+pub struct AxROM {
+    prg_rom: Banks,
+    chr: CHR,
+    bus_conflicts: bool,
+    register: u8,
+}
+
+impl AxROM {
+    const PRG_BANK_VALUE: u8 = 0b0000_0111;
+    const NAME_TABLE_VALUE: u8 = 0b0001_0000;
+
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, chr_ram_size: usize, bus_conflicts: bool) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_BANK_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CRH_PAGE_SIZE),
+            bus_conflicts,
+            register: 0,
+        }
+    }
+}
+
+impl Mapper for AxROM {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF => Some(
+                self.prg_rom
+                    .read((self.register & Self::PRG_BANK_VALUE) as usize, address - 0x8000),
+            ),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xFFFF = address {
+            self.register = match self.bus_conflicts {
+                true => bus_conflict(value, self.cpu_read(address).unwrap_or(0xFF)),
+                false => value,
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(0, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.register & Self::NAME_TABLE_VALUE) != 0 {
+            false => Mirroring::SingleScreenLower,
+            true => Mirroring::SingleScreenUpper,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::AxROM;
+    use crate::{
+        hardware::mapper::{tests::numbered_banks, Mapper},
+        rom::{Mirroring, PRG_PAGE_SIZE},
+    };
+
+    #[test]
+    pub fn test_axrom_mapper() {
+        // 32 KiB bank n is made of the 16 KiB banks 2n and 2n + 1
+        let mut mapper = AxROM::new(numbered_banks(PRG_PAGE_SIZE, 16), vec![], 0, false);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x00));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.cpu_write(0x8000, 0x15);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x0A));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x0B));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    pub fn test_axrom_bus_conflicts() {
+        let mut mapper = AxROM::new(numbered_banks(PRG_PAGE_SIZE, 16), vec![], 0, true);
+        mapper.cpu_write(0x8000, 0x13);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x00));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.cpu_write(0x8001, 0x13);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x06));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
@@ -0,0 +1,105 @@
+use super::{bus_conflict, Banks, Mapper, CHR};
+use crate::rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE};
+
+/// Mapper 3. Fixed PRG ROM like NROM and a switchable 8 KiB CHR bank
+pub struct CNROM {
+    prg_rom: Banks,
+    chr: CHR,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+impl CNROM {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_PAGE_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CRH_PAGE_SIZE),
+            mirroring,
+            bus_conflicts,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for CNROM {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xBFFF => Some(self.prg_rom.read(0, address - 0x8000)),
+            0xC000..=0xFFFF => Some(self.prg_rom.read(self.prg_rom.last(), address - 0xC000)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xFFFF = address {
+            self.chr_bank = match self.bus_conflicts {
+                true => bus_conflict(value, self.cpu_read(address).unwrap_or(0xFF)),
+                false => value,
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_bank as usize, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(self.chr_bank as usize, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::CNROM;
+    use crate::{
+        hardware::mapper::{tests::numbered_banks, Mapper},
+        rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE},
+    };
+
+    #[test]
+    pub fn test_cnrom_mapper() {
+        let mut mapper = CNROM::new(
+            vec![0u8; PRG_PAGE_SIZE * 2],
+            numbered_banks(CRH_PAGE_SIZE, 4),
+            0,
+            Mirroring::Horizontal,
+            false,
+        );
+        assert_eq!(mapper.ppu_read(0x0000), 0x00);
+
+        mapper.cpu_write(0x8000, 0x02);
+        assert_eq!(mapper.ppu_read(0x0000), 0x02);
+        mapper.cpu_write(0xFFFF, 0x07);
+        assert_eq!(mapper.ppu_read(0x0000), 0x03);
+    }
+
+    #[test]
+    pub fn test_cnrom_bus_conflicts() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0x0000] = 0x01;
+        prg_rom[0x0001] = 0x03;
+        let mut mapper = CNROM::new(
+            prg_rom,
+            numbered_banks(CRH_PAGE_SIZE, 4),
+            0,
+            Mirroring::Horizontal,
+            true,
+        );
+
+        mapper.cpu_write(0x8000, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 0x01);
+        mapper.cpu_write(0x8001, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 0x03);
+    }
+}
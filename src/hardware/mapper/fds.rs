@@ -0,0 +1,506 @@
+use super::{Banks, Mapper, CHR};
+use crate::rom::{
+    fds::{Disk, DISK_SIDE_SIZE},
+    Mirroring, CRH_PAGE_SIZE,
+};
+
+const PRG_RAM_SIZE: usize = 32 * 1024;
+pub const BIOS_SIZE: usize = 8 * 1024;
+/// Gaps on the physical disk before the first block and after every block, in bytes
+const LEADING_GAP_SIZE: usize = 28300 / 8;
+const BLOCK_GAP_SIZE: usize = 976 / 8;
+const GAP_END_MARK: u8 = 0x80;
+/// Stand-in for the CRC following every block, as the BIOS doesn't check it
+const BLOCK_CRC: [u8; 2] = [0x4D, 0x62];
+/// Room for the blocks, gaps and free space of a side, which takes the drive about 7 seconds to scan
+const SIDE_CAPACITY: usize = 80000;
+/// CPU cycles between two bytes passing under the head, and before the first one after the head is rewound
+const BYTE_CYCLES: u32 = 150;
+const REWIND_CYCLES: u32 = 50000;
+/// A swapped disk side stays ejected for about a second, so that the BIOS notices
+const DISK_SWAP_CYCLES: u32 = 1_789_773;
+
+/// Famicom Disk System RAM adapter with its 32 KiB PRG RAM, 8 KiB CHR RAM, timer IRQ and disk drive
+pub struct FDS {
+    bios: Banks,
+    prg_ram: Banks,
+    chr: CHR,
+    /// Disk sides with the gaps, block marks and CRCs the drive reads
+    sides: Vec<Vec<u8>>,
+    /// Inserted side, none while a side swap ejected the disk
+    side: Option<usize>,
+    next_side: usize,
+    disk_swap_cycles: u32,
+    master_io_enable: u8,
+    control: u8,
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+    disk_irq: bool,
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    head_position: usize,
+    head_delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    crc: u16,
+    previous_crc_control: bool,
+}
+
+impl FDS {
+    const DISK_REGISTERS_ENABLE_VALUE: u8 = 0b0000_0001;
+    const MOTOR_ON_VALUE: u8 = 0b0000_0001;
+    const TRANSFER_RESET_VALUE: u8 = 0b0000_0010;
+    const READ_MODE_VALUE: u8 = 0b0000_0100;
+    const HORIZONTAL_MIRRORING_VALUE: u8 = 0b0000_1000;
+    const CRC_CONTROL_VALUE: u8 = 0b0001_0000;
+    const DISK_READY_VALUE: u8 = 0b0100_0000;
+    const DISK_IRQ_ENABLE_VALUE: u8 = 0b1000_0000;
+
+    pub fn new(bios: Vec<u8>, disk: &Disk) -> Self {
+        Self {
+            bios: Banks::new(bios, BIOS_SIZE),
+            prg_ram: Banks::new(vec![0; PRG_RAM_SIZE], PRG_RAM_SIZE),
+            chr: CHR::new(vec![], CRH_PAGE_SIZE, CRH_PAGE_SIZE),
+            sides: disk.sides().iter().map(|side| add_gaps(side)).collect(),
+            side: Some(0),
+            next_side: 0,
+            disk_swap_cycles: 0,
+            master_io_enable: Self::DISK_REGISTERS_ENABLE_VALUE,
+            control: 0,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            disk_irq: false,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            head_position: 0,
+            head_delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            crc: 0,
+            previous_crc_control: false,
+        }
+    }
+
+    fn control_flag(&self, value: u8) -> bool {
+        (self.control & value) != 0
+    }
+
+    fn disk_registers_enabled(&self) -> bool {
+        (self.master_io_enable & Self::DISK_REGISTERS_ENABLE_VALUE) != 0
+    }
+
+    fn step_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        match self.irq_counter {
+            0 => {
+                self.timer_irq = true;
+                self.irq_counter = self.irq_reload;
+                self.irq_enabled = self.irq_repeat;
+            }
+            _ => self.irq_counter -= 1,
+        }
+    }
+
+    fn step_drive(&mut self) {
+        let side = match self.side {
+            Some(side) if self.control_flag(Self::MOTOR_ON_VALUE) => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.control_flag(Self::TRANSFER_RESET_VALUE) && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.head_delay = REWIND_CYCLES;
+            self.head_position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.head_delay > 0 {
+            self.head_delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let irq_enabled = self.control_flag(Self::DISK_IRQ_ENABLE_VALUE);
+        let crc_control = self.control_flag(Self::CRC_CONTROL_VALUE);
+        let disk_ready = self.control_flag(Self::DISK_READY_VALUE);
+        match self.control_flag(Self::READ_MODE_VALUE) {
+            true => {
+                let value = self.sides[side].get(self.head_position).copied().unwrap_or(0);
+                let mut irq = irq_enabled;
+                if !disk_ready {
+                    self.gap_ended = false;
+                } else if value != 0 && !self.gap_ended {
+                    // The gap end mark itself doesn't raise an IRQ
+                    self.gap_ended = true;
+                    irq = false;
+                }
+                if self.gap_ended {
+                    self.transfer_complete = true;
+                    self.read_data = value;
+                    self.disk_irq |= irq;
+                }
+            }
+            false => {
+                let mut value = self.write_data;
+                if !crc_control {
+                    self.transfer_complete = true;
+                    self.disk_irq |= irq_enabled;
+                }
+                if !disk_ready {
+                    value = 0;
+                    self.crc = 0;
+                }
+                match crc_control {
+                    false => self.update_crc(value),
+                    true => {
+                        if !self.previous_crc_control {
+                            self.update_crc(0);
+                            self.update_crc(0);
+                        }
+                        value = self.crc as u8;
+                        self.crc >>= 8;
+                    }
+                }
+                // The head writes a little behind the byte being transferred
+                if let Some(byte) = self
+                    .head_position
+                    .checked_sub(2)
+                    .and_then(|position| self.sides[side].get_mut(position))
+                {
+                    *byte = value;
+                }
+                self.gap_ended = false;
+            }
+        }
+        self.previous_crc_control = crc_control;
+
+        self.head_position += 1;
+        match self.head_position >= self.sides[side].len() {
+            true => self.control &= !Self::MOTOR_ON_VALUE,
+            false => self.head_delay = BYTE_CYCLES,
+        }
+    }
+
+    /// CRC-16 with the reversed polynomial $8408 that the RAM adapter appends to written blocks
+    fn update_crc(&mut self, value: u8) {
+        for bit in 0..8 {
+            let carry = (self.crc & 0x0001) != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= 0x8408;
+            }
+            if (value >> bit) & 0x01 != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+}
+
+impl Mapper for FDS {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4030 if self.disk_registers_enabled() => {
+                let value = self.timer_irq as u8 | (self.transfer_complete as u8) << 1 | (self.end_of_head as u8) << 6;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                Some(value)
+            }
+            0x4031 if self.disk_registers_enabled() => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(self.read_data)
+            }
+            0x4032 if self.disk_registers_enabled() => {
+                let ejected = self.side.is_none();
+                // Bit 2 reports ejected disks as write protected
+                Some(ejected as u8 | ((ejected || !self.scanning) as u8) << 1 | (ejected as u8) << 2)
+            }
+            // Bit 7 tells the battery of the RAM adapter is good
+            0x4033 if self.disk_registers_enabled() => Some(0x80),
+            0x6000..=0xDFFF => Some(self.prg_ram.read(0, address - 0x6000)),
+            0xE000..=0xFFFF => Some(self.bios.read(0, address - 0xE000)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (value as u16) << 8,
+            0x4022 if self.disk_registers_enabled() => {
+                self.irq_repeat = (value & 0x01) != 0;
+                self.irq_enabled = (value & 0x02) != 0;
+                match self.irq_enabled {
+                    true => self.irq_counter = self.irq_reload,
+                    false => self.timer_irq = false,
+                }
+            }
+            0x4023 => {
+                self.master_io_enable = value;
+                if !self.disk_registers_enabled() {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled() => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled() => {
+                self.control = value;
+                self.disk_irq = false;
+            }
+            0x6000..=0xDFFF => self.prg_ram.write(0, address - 0x6000, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(0, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control_flag(Self::HORIZONTAL_MIRRORING_VALUE) {
+            false => Mirroring::Vertical,
+            true => Mirroring::Horizontal,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn notify_cpu_cycle(&mut self) {
+        self.step_timer();
+        match self.disk_swap_cycles {
+            0 => self.step_drive(),
+            1 => {
+                self.disk_swap_cycles = 0;
+                self.side = Some(self.next_side);
+            }
+            _ => self.disk_swap_cycles -= 1,
+        }
+    }
+
+    fn switch_disk_side(&mut self) {
+        if let Some(side) = self.side {
+            self.disk_swap_cycles = DISK_SWAP_CYCLES;
+            self.next_side = (side + 1) % self.sides.len();
+            self.side = None;
+        }
+    }
+
+    /// The disk image without gaps, in the format of headerless .fds files
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(
+            self.sides
+                .iter()
+                .map(|side| remove_gaps(side))
+                .collect::<Vec<_>>()
+                .concat(),
+        )
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Ok(disk) = Disk::with_content(data) {
+            self.sides = disk.sides().iter().map(|side| add_gaps(side)).collect();
+        }
+    }
+}
+
+/// Length of the block starting at `offset` of a disk side without gaps, which depends on its type
+fn block_length(side: &[u8], offset: usize) -> Option<usize> {
+    match side.get(offset)? {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        // The size of a file is in the header block before it
+        4 => {
+            Some(1 + (*side.get(offset.checked_sub(3)?)? as usize | (*side.get(offset.checked_sub(2)?)? as usize) << 8))
+        }
+        _ => None,
+    }
+}
+
+/// Lays out the blocks of a disk side as the drive sees them, between gaps and followed by their CRC
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut disk = vec![0; LEADING_GAP_SIZE];
+    let mut offset = 0;
+    while let Some(length) = block_length(side, offset) {
+        let block = match side.get(offset..offset + length) {
+            Some(block) => block,
+            None => break,
+        };
+        disk.push(GAP_END_MARK);
+        disk.extend_from_slice(block);
+        disk.extend_from_slice(&BLOCK_CRC);
+        disk.resize(disk.len() + BLOCK_GAP_SIZE, 0);
+        offset += length;
+    }
+    disk.resize(disk.len().max(SIDE_CAPACITY), 0);
+    disk
+}
+
+fn remove_gaps(disk: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(DISK_SIDE_SIZE);
+    let mut offset = 0;
+    // Blocks start after the gap end mark, the first byte that isn't zero
+    while let Some(mark) = disk
+        .get(offset..)
+        .and_then(|gap| gap.iter().position(|value| *value != 0))
+    {
+        let start = offset + mark + 1;
+        let block_start = side.len();
+        side.push(disk.get(start).copied().unwrap_or(0));
+        let block = block_length(&side, block_start).and_then(|length| disk.get(start + 1..start + length));
+        match block {
+            Some(block) => side.extend_from_slice(block),
+            None => {
+                side.truncate(block_start);
+                break;
+            }
+        }
+        offset = side.len() - block_start + start + BLOCK_CRC.len();
+    }
+    side.resize(DISK_SIDE_SIZE, 0);
+    side
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{add_gaps, remove_gaps, BIOS_SIZE, FDS, LEADING_GAP_SIZE};
+    use crate::{
+        hardware::mapper::Mapper,
+        rom::fds::{Disk, DISK_SIDE_SIZE},
+    };
+
+    fn disk_side(file: &[u8]) -> Vec<u8> {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(56, 0x00);
+        side.extend_from_slice(&[0x02, 0x01]);
+        let mut file_header = vec![0x03, 0x00, 0x00];
+        file_header.extend_from_slice(b"FILE    ");
+        file_header.extend_from_slice(&[0x00, 0x60, file.len() as u8, (file.len() >> 8) as u8, 0x00]);
+        side.extend_from_slice(&file_header);
+        side.push(0x04);
+        side.extend_from_slice(file);
+        side.resize(DISK_SIDE_SIZE, 0x00);
+        side
+    }
+
+    fn fds(sides: &[Vec<u8>]) -> FDS {
+        FDS::new(vec![0xEA; BIOS_SIZE], &Disk::with_content(&sides.concat()).unwrap())
+    }
+
+    #[test]
+    pub fn test_fds_gaps() {
+        let side = disk_side(&[0xAA; 0x0300]);
+        let disk = add_gaps(&side);
+        assert_eq!(
+            disk[LEADING_GAP_SIZE - 1..LEADING_GAP_SIZE + 3],
+            [0x00, 0x80, 0x01, b'*']
+        );
+        assert_eq!(remove_gaps(&disk), side);
+    }
+
+    #[test]
+    pub fn test_fds_memory() {
+        let mut mapper = fds(&[disk_side(&[])]);
+        assert_eq!(mapper.cpu_read(0xE000), Some(0xEA));
+        mapper.cpu_write(0x6000, 0x01);
+        mapper.cpu_write(0xDFFF, 0x02);
+        mapper.cpu_write(0xE000, 0x03);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+        assert_eq!(mapper.cpu_read(0xDFFF), Some(0x02));
+        assert_eq!(mapper.cpu_read(0xE000), Some(0xEA));
+
+        mapper.ppu_write(0x1000, 0x04);
+        assert_eq!(mapper.ppu_read(0x1000), 0x04);
+    }
+
+    #[test]
+    pub fn test_fds_timer_irq() {
+        let mut mapper = fds(&[disk_side(&[])]);
+        mapper.cpu_write(0x4020, 0x02);
+        mapper.cpu_write(0x4021, 0x00);
+        mapper.cpu_write(0x4022, 0x03);
+        for _ in 0..2 {
+            mapper.notify_cpu_cycle();
+        }
+        assert!(!mapper.irq());
+        mapper.notify_cpu_cycle();
+        assert!(mapper.irq());
+        assert_eq!(mapper.cpu_read(0x4030).map(|value| value & 0x01), Some(0x01));
+        assert!(!mapper.irq());
+
+        // Repeats until disabled
+        for _ in 0..3 {
+            mapper.notify_cpu_cycle();
+        }
+        assert!(mapper.irq());
+        mapper.cpu_write(0x4022, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    pub fn test_fds_disk_read() {
+        let mut mapper = fds(&[disk_side(&[])]);
+        // Motor on, read mode, disk ready
+        mapper.cpu_write(0x4025, 0b0100_0101);
+        let mut data = Vec::new();
+        while data.len() < 4 {
+            mapper.notify_cpu_cycle();
+            if mapper.cpu_read(0x4030).map(|value| value & 0x02) == Some(0x02) {
+                data.push(mapper.cpu_read(0x4031).unwrap());
+            }
+        }
+        assert_eq!(data, [0x80, 0x01, b'*', b'N']);
+        assert_eq!(mapper.cpu_read(0x4032).map(|value| value & 0x07), Some(0x00));
+    }
+
+    #[test]
+    pub fn test_fds_disk_side_switch() {
+        let mut mapper = fds(&[disk_side(&[0x01]), disk_side(&[0x02])]);
+        mapper.switch_disk_side();
+        assert_eq!(mapper.cpu_read(0x4032).map(|value| value & 0x07), Some(0x07));
+        while mapper.cpu_read(0x4032).map(|value| value & 0x01) == Some(0x01) {
+            mapper.notify_cpu_cycle();
+        }
+        assert_eq!(mapper.side, Some(1));
+    }
+
+    #[test]
+    pub fn test_fds_save_data() {
+        let sides = [disk_side(&[0x01]), disk_side(&[0x02])];
+        let mut mapper = fds(&sides);
+        assert_eq!(mapper.save_data(), Some(sides.concat()));
+
+        let modified = [disk_side(&[0x03, 0x04]), disk_side(&[0x02])];
+        mapper.load_save_data(&modified.concat());
+        assert_eq!(mapper.save_data(), Some(modified.concat()));
+    }
+}
@@ -0,0 +1,353 @@
+use super::{Banks, Mapper, CHR};
+use crate::rom::{Mirroring, PRG_PAGE_SIZE, PRG_RAM_PAGE_SIZE};
+
+const CHR_BANK_SIZE: usize = 4 * 1024;
+/// Boards with more PRG ROM than this (SUROM, SXROM) use a CHR bank bit to select the 256 KiB outer PRG bank
+const PRG_OUTER_BANK_SIZE: usize = 256 * 1024;
+
+/// Mapper 1. Its registers are loaded serially through a 5-bit shift register written one bit at a time
+pub struct MMC1 {
+    prg_rom: Banks,
+    chr: CHR,
+    prg_ram: Banks,
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    cpu_cycle: u64,
+    last_write_cycle: Option<u64>,
+}
+
+impl MMC1 {
+    const RESET_VALUE: u8 = 0b1000_0000;
+    const PRG_RAM_DISABLE_VALUE: u8 = 0b0001_0000;
+
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, chr_ram_size: usize, prg_ram_size: usize) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_PAGE_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CHR_BANK_SIZE),
+            prg_ram: Banks::new(vec![0; prg_ram_size], PRG_RAM_PAGE_SIZE),
+            shift_register: 0,
+            shift_count: 0,
+            // The last PRG bank is fixed at $C000 at power up so the reset vector is reachable
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            cpu_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn control_flags(&self) -> ControlFlags {
+        ControlFlags::from(self.control)
+    }
+
+    fn write_shift_register(&mut self, address: u16, value: u8) {
+        // The serial port ignores writes on consecutive cycles, like the dummy write of read-modify-write
+        // instructions, so only the first of the two writes counts
+        let consecutive = self.last_write_cycle == Some(self.cpu_cycle);
+        self.last_write_cycle = Some(self.cpu_cycle);
+        if consecutive {
+            return;
+        }
+
+        if (value & Self::RESET_VALUE) != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            return;
+        }
+
+        self.shift_register |= (value & 0x01) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count == 5 {
+            let value = self.shift_register;
+            match address {
+                0x8000..=0x9FFF => self.control = value,
+                0xA000..=0xBFFF => self.chr_bank_0 = value,
+                0xC000..=0xDFFF => self.chr_bank_1 = value,
+                _ => self.prg_bank = value,
+            }
+            self.shift_register = 0;
+            self.shift_count = 0;
+        }
+    }
+
+    /// SUROM and SXROM select the 256 KiB half of PRG ROM with bit 4 of the CHR bank. In 4 KiB CHR mode the
+    /// hardware uses whichever CHR register the PPU last fetched with, but games keep both equal
+    fn prg_outer_bank(&self) -> usize {
+        match self.prg_rom.count() * PRG_PAGE_SIZE > PRG_OUTER_BANK_SIZE {
+            true => (self.chr_bank_0 & 0x10) as usize,
+            false => 0,
+        }
+    }
+
+    fn prg_rom_bank(&self, address: u16) -> usize {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let bank = match (self.control_flags().prg_rom_bank_mode, address) {
+            (PRGROMBankMode::Switch32K, 0x8000..=0xBFFF) => bank & 0x0E,
+            (PRGROMBankMode::Switch32K, _) => bank | 0x01,
+            (PRGROMBankMode::FixFirst, 0x8000..=0xBFFF) => 0x00,
+            (PRGROMBankMode::FixFirst, _) => bank,
+            (PRGROMBankMode::FixLast, 0x8000..=0xBFFF) => bank,
+            (PRGROMBankMode::FixLast, _) => 0x0F,
+        };
+        self.prg_outer_bank() | bank
+    }
+
+    /// SOROM and SXROM select the 8 KiB PRG RAM bank with bits 2-3 of the CHR bank
+    fn prg_ram_bank(&self) -> usize {
+        match self.prg_ram.count() {
+            2 => ((self.chr_bank_0 >> 3) & 0x01) as usize,
+            _ => ((self.chr_bank_0 >> 2) & 0x03) as usize,
+        }
+    }
+
+    /// SNROM disables PRG RAM with bit 4 of the CHR bank, which is unused for its 8 KiB of CHR
+    fn prg_ram_enabled(&self) -> bool {
+        let snrom_disabled = self.chr.count() <= 2
+            && self.prg_rom.count() * PRG_PAGE_SIZE <= PRG_OUTER_BANK_SIZE
+            && (self.chr_bank_0 & 0x10) != 0;
+        (self.prg_bank & Self::PRG_RAM_DISABLE_VALUE) == 0 && !snrom_disabled
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        match (self.control_flags().chr_4k_banks, address) {
+            (true, 0x0000..=0x0FFF) => self.chr_bank_0 as usize,
+            (true, _) => self.chr_bank_1 as usize,
+            (false, 0x0000..=0x0FFF) => (self.chr_bank_0 & 0x1E) as usize,
+            (false, _) => (self.chr_bank_0 | 0x01) as usize,
+        }
+    }
+}
+
+impl Mapper for MMC1 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram.read(self.prg_ram_bank(), address - 0x6000)),
+            0x8000..=0xFFFF => Some(self.prg_rom.read(self.prg_rom_bank(address), address & 0x3FFF)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram.write(self.prg_ram_bank(), address - 0x6000, value)
+            }
+            0x8000..=0xFFFF => self.write_shift_register(address, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_bank(address), address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(self.chr_bank(address), address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.control_flags().mirroring
+    }
+
+    fn notify_cpu_cycle(&mut self) {
+        self.cpu_cycle += 1;
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(self.prg_ram.memory())
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.memory_mut())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum PRGROMBankMode {
+    Switch32K,
+    FixFirst,
+    FixLast,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct ControlFlags {
+    mirroring: Mirroring,
+    prg_rom_bank_mode: PRGROMBankMode,
+    chr_4k_banks: bool,
+}
+
+impl ControlFlags {
+    const PRG_ROM_BANK_MODE_VALUE: u8 = 0b0000_1100;
+    const CHR_4K_BANKS_VALUE: u8 = 0b0001_0000;
+}
+
+impl From<u8> for ControlFlags {
+    fn from(value: u8) -> Self {
+        Self {
+            mirroring: match value & 0x03 {
+                0 => Mirroring::SingleScreenLower,
+                1 => Mirroring::SingleScreenUpper,
+                2 => Mirroring::Vertical,
+                _ => Mirroring::Horizontal,
+            },
+            prg_rom_bank_mode: match (value & Self::PRG_ROM_BANK_MODE_VALUE) >> 2 {
+                0 | 1 => PRGROMBankMode::Switch32K,
+                2 => PRGROMBankMode::FixFirst,
+                _ => PRGROMBankMode::FixLast,
+            },
+            chr_4k_banks: (value & Self::CHR_4K_BANKS_VALUE) != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::MMC1;
+    use crate::{
+        hardware::mapper::Mapper,
+        rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE, PRG_RAM_PAGE_SIZE},
+    };
+
+    /// Writes `value` serially to the register mapped at `address`, one CPU cycle apart
+    fn write_register(mapper: &mut MMC1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.notify_cpu_cycle();
+            mapper.cpu_write(address, value >> bit);
+        }
+    }
+
+    /// Each 16 KiB PRG bank and 4 KiB CHR bank starts with its own index
+    fn mmc1(prg_rom_size: usize, chr_rom_size: usize, prg_ram_size: usize) -> MMC1 {
+        let mut prg_rom = vec![0u8; prg_rom_size];
+        for (index, bank) in prg_rom.chunks_exact_mut(PRG_PAGE_SIZE).enumerate() {
+            bank[0] = index as u8;
+        }
+        let mut chr_rom = vec![0u8; chr_rom_size];
+        for (index, bank) in chr_rom.chunks_exact_mut(0x1000).enumerate() {
+            bank[0] = index as u8;
+        }
+        MMC1::new(prg_rom, chr_rom, 0, prg_ram_size)
+    }
+
+    #[test]
+    pub fn test_mmc1_prg_banks() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 8, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x00));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x07));
+
+        write_register(&mut mapper, 0xE000, 0x02);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x02));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x07));
+
+        write_register(&mut mapper, 0x8000, 0b0_1000);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x00));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x02));
+
+        write_register(&mut mapper, 0x8000, 0b0_0000);
+        write_register(&mut mapper, 0xE000, 0x05);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x04));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x05));
+    }
+
+    #[test]
+    pub fn test_mmc1_chr_banks() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 2, CRH_PAGE_SIZE * 4, PRG_RAM_PAGE_SIZE);
+        write_register(&mut mapper, 0xA000, 0x03);
+        write_register(&mut mapper, 0xC000, 0x05);
+        assert_eq!(mapper.ppu_read(0x0000), 0x02);
+        assert_eq!(mapper.ppu_read(0x1000), 0x03);
+
+        write_register(&mut mapper, 0x8000, 0b1_1100);
+        assert_eq!(mapper.ppu_read(0x0000), 0x03);
+        assert_eq!(mapper.ppu_read(0x1000), 0x05);
+    }
+
+    #[test]
+    pub fn test_mmc1_mirroring() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 2, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        write_register(&mut mapper, 0x8000, 0b0_1100);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+        write_register(&mut mapper, 0x8000, 0b0_1101);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        write_register(&mut mapper, 0x8000, 0b0_1110);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        write_register(&mut mapper, 0x8000, 0b0_1111);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    pub fn test_mmc1_shift_register_reset() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 8, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        write_register(&mut mapper, 0x8000, 0b0_0010);
+        mapper.notify_cpu_cycle();
+        mapper.cpu_write(0xE000, 0x01);
+        mapper.notify_cpu_cycle();
+        mapper.cpu_write(0xE000, 0x80);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        // The reset went back to fixing the last bank and dropped the pending bit
+        write_register(&mut mapper, 0xE000, 0x02);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x02));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x07));
+    }
+
+    #[test]
+    pub fn test_mmc1_consecutive_writes() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 8, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        for bit in [0x01, 0x00, 0x01, 0x00, 0x00] {
+            mapper.notify_cpu_cycle();
+            mapper.cpu_write(0xE000, bit);
+            mapper.cpu_write(0xE000, 0x01);
+        }
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x05));
+    }
+
+    #[test]
+    pub fn test_mmc1_prg_ram() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 2, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        mapper.cpu_write(0x6000, 0x01);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+
+        write_register(&mut mapper, 0xE000, 0x10);
+        mapper.cpu_write(0x6000, 0x02);
+        assert_eq!(mapper.cpu_read(0x6000), None);
+
+        write_register(&mut mapper, 0xE000, 0x00);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+
+        // SNROM
+        write_register(&mut mapper, 0xA000, 0x10);
+        assert_eq!(mapper.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    pub fn test_mmc1_surom() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 32, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE);
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x0F));
+
+        write_register(&mut mapper, 0xA000, 0x10);
+        write_register(&mut mapper, 0xE000, 0x01);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x11));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x1F));
+        // The PRG RAM stays enabled, as the bit selects PRG ROM on this board
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x00));
+    }
+
+    #[test]
+    pub fn test_mmc1_sorom() {
+        let mut mapper = mmc1(PRG_PAGE_SIZE * 16, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE * 2);
+        mapper.cpu_write(0x6000, 0x01);
+        write_register(&mut mapper, 0xA000, 0x08);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x00));
+        mapper.cpu_write(0x6000, 0x02);
+
+        write_register(&mut mapper, 0xA000, 0x00);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+    }
+}
@@ -0,0 +1,388 @@
+use super::{Banks, Mapper, CHR};
+use crate::rom::{Mirroring, PRG_RAM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+/// The scanline counter only sees an A12 rising edge after A12 stayed low for a few CPU cycles, which filters out
+/// the toggling between consecutive pattern fetches
+const A12_FILTER_CYCLES: u64 = 4;
+
+/// Behaviour of the IRQ counter when it is reloaded with 0
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IRQRevision {
+    /// MMC3A (NEC), which only fires when the counter is decremented to 0 or reloaded through $C001
+    Old,
+    /// MMC3B and MMC3C (Sharp), which fire whenever the counter is 0 after being clocked
+    New,
+}
+
+/// Mapper 4. 8 KiB PRG and 1 KiB CHR banks, and a scanline counter clocked by rising edges of PPU A12
+pub struct MMC3 {
+    prg_rom: Banks,
+    chr: CHR,
+    prg_ram: Banks,
+    header_mirroring: Mirroring,
+    irq_revision: IRQRevision,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    mirroring: u8,
+    prg_ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    cpu_cycle: u64,
+    last_a12_high_cycle: u64,
+}
+
+impl MMC3 {
+    const BANK_REGISTER_VALUE: u8 = 0b0000_0111;
+    const PRG_ROM_BANK_MODE_VALUE: u8 = 0b0100_0000;
+    const CHR_A12_INVERSION_VALUE: u8 = 0b1000_0000;
+    const PRG_RAM_WRITE_PROTECT_VALUE: u8 = 0b0100_0000;
+    const PRG_RAM_ENABLE_VALUE: u8 = 0b1000_0000;
+
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        chr_ram_size: usize,
+        prg_ram_size: usize,
+        header_mirroring: Mirroring,
+        irq_revision: IRQRevision,
+    ) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_BANK_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CHR_BANK_SIZE),
+            prg_ram: Banks::new(vec![0; prg_ram_size], PRG_RAM_PAGE_SIZE),
+            header_mirroring,
+            irq_revision,
+            bank_select: 0,
+            bank_registers: [0; 8],
+            mirroring: 0,
+            // Games that never touch $A001 still expect their PRG RAM to work
+            prg_ram_protect: Self::PRG_RAM_ENABLE_VALUE,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            cpu_cycle: 0,
+            last_a12_high_cycle: 0,
+        }
+    }
+
+    fn prg_rom_bank(&self, address: u16) -> usize {
+        let second_last = self.prg_rom.count().saturating_sub(2);
+        let swapped = (self.bank_select & Self::PRG_ROM_BANK_MODE_VALUE) != 0;
+        match (address, swapped) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => (self.bank_registers[6] & 0x3F) as usize,
+            (0xA000..=0xBFFF, _) => (self.bank_registers[7] & 0x3F) as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            _ => self.prg_rom.last(),
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        // The inversion swaps the two 2 KiB banks at $0000 with the four 1 KiB banks at $1000
+        let address = match (self.bank_select & Self::CHR_A12_INVERSION_VALUE) != 0 {
+            true => address ^ 0x1000,
+            false => address,
+        };
+        match address {
+            0x0000..=0x07FF => (self.bank_registers[0] & 0xFE) as usize + ((address as usize >> 10) & 0x01),
+            0x0800..=0x0FFF => (self.bank_registers[1] & 0xFE) as usize + ((address as usize >> 10) & 0x01),
+            _ => self.bank_registers[2 + ((address as usize >> 10) & 0x03)] as usize,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.prg_ram_protect & Self::PRG_RAM_ENABLE_VALUE) != 0
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_enabled() && (self.prg_ram_protect & Self::PRG_RAM_WRITE_PROTECT_VALUE) == 0
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match (address, (address & 0x01) == 0) {
+            (0x8000..=0x9FFF, true) => self.bank_select = value,
+            (0x8000..=0x9FFF, false) => {
+                self.bank_registers[(self.bank_select & Self::BANK_REGISTER_VALUE) as usize] = value
+            }
+            (0xA000..=0xBFFF, true) => self.mirroring = value,
+            (0xA000..=0xBFFF, false) => self.prg_ram_protect = value,
+            (0xC000..=0xDFFF, true) => self.irq_latch = value,
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (_, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (_, false) => self.irq_enabled = true,
+        }
+    }
+
+    /// Clocks the scanline counter on filtered A12 rising edges of the PPU's address bus
+    fn watch_a12(&mut self, address: u16) {
+        if (address & 0x1000) == 0 {
+            return;
+        }
+        if self.cpu_cycle - self.last_a12_high_cycle >= A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        self.last_a12_high_cycle = self.cpu_cycle;
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let reloaded = self.irq_reload;
+        let old_counter = self.irq_counter;
+        self.irq_counter = match self.irq_counter == 0 || self.irq_reload {
+            true => self.irq_latch,
+            false => self.irq_counter - 1,
+        };
+        self.irq_reload = false;
+
+        let fires = match self.irq_revision {
+            IRQRevision::Old => self.irq_counter == 0 && (old_counter != 0 || reloaded),
+            IRQRevision::New => self.irq_counter == 0,
+        };
+        if fires && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for MMC3 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram.read(0, address - 0x6000)),
+            0x8000..=0xFFFF => Some(self.prg_rom.read(self.prg_rom_bank(address), address & 0x1FFF)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_writable() => self.prg_ram.write(0, address - 0x6000, value),
+            0x8000..=0xFFFF => self.write_register(address, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.watch_a12(address);
+        self.chr.read(self.chr_bank(address), address & 0x03FF)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.watch_a12(address);
+        self.chr.write(self.chr_bank(address), address & 0x03FF, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.header_mirroring, self.mirroring & 0x01) {
+            (Mirroring::FourScreen, _) => Mirroring::FourScreen,
+            (_, 0) => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn notify_cpu_cycle(&mut self) {
+        self.cpu_cycle += 1;
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(self.prg_ram.memory())
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.memory_mut())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{IRQRevision, MMC3};
+    use crate::{
+        hardware::{
+            mapper::Mapper,
+            ppu::{PPU, PPU_DOTS_PER_CPU_CYCLE},
+        },
+        rom::{Mirroring, PRG_RAM_PAGE_SIZE},
+    };
+
+    /// Each 8 KiB PRG bank and 1 KiB CHR bank starts with its own index
+    fn mmc3(irq_revision: IRQRevision) -> MMC3 {
+        let mut prg_rom = vec![0u8; 0x2000 * 16];
+        for (index, bank) in prg_rom.chunks_exact_mut(0x2000).enumerate() {
+            bank[0] = index as u8;
+        }
+        let mut chr_rom = vec![0u8; 0x0400 * 32];
+        for (index, bank) in chr_rom.chunks_exact_mut(0x0400).enumerate() {
+            bank[0] = index as u8;
+        }
+        MMC3::new(
+            prg_rom,
+            chr_rom,
+            0,
+            PRG_RAM_PAGE_SIZE,
+            Mirroring::Horizontal,
+            irq_revision,
+        )
+    }
+
+    /// Simulates a scanline of rendering with the background at $0000 and sprites at $1000
+    fn scanline(mapper: &mut MMC3) {
+        for _ in 0..85 {
+            mapper.notify_cpu_cycle();
+            mapper.ppu_read(0x0000);
+        }
+        for _ in 0..8 {
+            mapper.notify_cpu_cycle();
+            mapper.ppu_read(0x1000);
+            mapper.ppu_read(0x1008);
+            mapper.notify_cpu_cycle();
+        }
+        for _ in 0..21 {
+            mapper.notify_cpu_cycle();
+        }
+    }
+
+    #[test]
+    pub fn test_mmc3_prg_banks() {
+        let mut mapper = mmc3(IRQRevision::New);
+        mapper.cpu_write(0x8000, 0x06);
+        mapper.cpu_write(0x8001, 0x03);
+        mapper.cpu_write(0x8000, 0x07);
+        mapper.cpu_write(0x8001, 0x05);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x03));
+        assert_eq!(mapper.cpu_read(0xA000), Some(0x05));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x0E));
+        assert_eq!(mapper.cpu_read(0xE000), Some(0x0F));
+
+        mapper.cpu_write(0x8000, 0x40);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x0E));
+        assert_eq!(mapper.cpu_read(0xA000), Some(0x05));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x03));
+        assert_eq!(mapper.cpu_read(0xE000), Some(0x0F));
+    }
+
+    #[test]
+    pub fn test_mmc3_chr_banks() {
+        let mut mapper = mmc3(IRQRevision::New);
+        for (register, bank) in [0x03, 0x08, 0x10, 0x11, 0x12, 0x13].iter().enumerate() {
+            mapper.cpu_write(0x8000, register as u8);
+            mapper.cpu_write(0x8001, *bank);
+        }
+        let banks = |mapper: &mut MMC3| -> Vec<u8> { (0..8).map(|index| mapper.ppu_read(index * 0x0400)).collect() };
+        assert_eq!(banks(&mut mapper), [0x02, 0x03, 0x08, 0x09, 0x10, 0x11, 0x12, 0x13]);
+
+        mapper.cpu_write(0x8000, 0x80);
+        assert_eq!(banks(&mut mapper), [0x10, 0x11, 0x12, 0x13, 0x02, 0x03, 0x08, 0x09]);
+    }
+
+    #[test]
+    pub fn test_mmc3_mirroring() {
+        let mut mapper = mmc3(IRQRevision::New);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        mapper.cpu_write(0xA000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        let mut mapper = MMC3::new(vec![], vec![], 0, 0, Mirroring::FourScreen, IRQRevision::New);
+        mapper.cpu_write(0xA000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    pub fn test_mmc3_prg_ram_protect() {
+        let mut mapper = mmc3(IRQRevision::New);
+        mapper.cpu_write(0x6000, 0x01);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+
+        mapper.cpu_write(0xA001, 0xC0);
+        mapper.cpu_write(0x6000, 0x02);
+        assert_eq!(mapper.cpu_read(0x6000), Some(0x01));
+
+        mapper.cpu_write(0xA001, 0x00);
+        assert_eq!(mapper.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    pub fn test_mmc3_irq() {
+        let mut mapper = mmc3(IRQRevision::New);
+        mapper.cpu_write(0xC000, 0x02);
+        mapper.cpu_write(0xC001, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+
+        // Reloads to 2, then counts down to 1 and 0
+        scanline(&mut mapper);
+        scanline(&mut mapper);
+        assert!(!mapper.irq());
+        scanline(&mut mapper);
+        assert!(mapper.irq());
+
+        mapper.cpu_write(0xE000, 0x00);
+        assert!(!mapper.irq());
+        // The counter reloads and keeps counting while IRQs are disabled
+        for _ in 0..3 {
+            scanline(&mut mapper);
+        }
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    pub fn test_mmc3_irq_revisions() {
+        // A latch of 0 fires on every scanline with the new behaviour, and only after $C001 with the old one
+        let mut mapper = mmc3(IRQRevision::New);
+        mapper.cpu_write(0xC001, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+        scanline(&mut mapper);
+        assert!(mapper.irq());
+        mapper.cpu_write(0xE000, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+        scanline(&mut mapper);
+        assert!(mapper.irq());
+
+        let mut mapper = mmc3(IRQRevision::Old);
+        mapper.cpu_write(0xC001, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+        scanline(&mut mapper);
+        assert!(mapper.irq());
+        mapper.cpu_write(0xE000, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+        scanline(&mut mapper);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    pub fn test_mmc3_irq_with_ppu() {
+        let mut mapper = mmc3(IRQRevision::New);
+        mapper.cpu_write(0xC000, 0x0A);
+        mapper.cpu_write(0xC001, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2000, 0b0000_1000, None);
+        ppu.write_register(0x2001, 0b0001_1000, None);
+
+        // The PPU powers up on scanline 0, where the sprite fetches reload the counter
+        let mut irq_scanline = None;
+        while irq_scanline.is_none() {
+            mapper.notify_cpu_cycle();
+            for _ in 0..PPU_DOTS_PER_CPU_CYCLE {
+                ppu.step(Some(&mut mapper));
+            }
+            if mapper.irq() {
+                irq_scanline = Some(ppu.clock.scanline);
+            }
+        }
+        assert_eq!(irq_scanline, Some(10));
+    }
+}
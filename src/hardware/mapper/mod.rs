@@ -0,0 +1,412 @@
+mod axrom;
+mod cnrom;
+mod fds;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod nsf;
+mod uxrom;
+
+pub use axrom::AxROM;
+pub use cnrom::CNROM;
+pub use fds::FDS;
+pub use mmc1::MMC1;
+pub use mmc3::{IRQRevision, MMC3};
+pub use nrom::NROM;
+pub use nsf::NSFCartridge;
+pub use uxrom::UxROM;
+
+use crate::{
+    error::ROMError,
+    rom::{fds::Disk, unif::UNIF, Mirroring, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE, ROM, TRAINER_SIZE},
+};
+
+/// The cartridge board, which decodes the CPU's $4020-$FFFF and the PPU's $0000-$1FFF address ranges.
+/// Mappers own the cartridge memory, so `&mut dyn Mapper` can be reborrowed freely
+pub trait Mapper: 'static {
+    fn cpu_read(&mut self, address: u16) -> Option<u8>;
+    fn cpu_write(&mut self, address: u16, value: u8);
+    fn ppu_read(&mut self, address: u16) -> u8;
+    fn ppu_write(&mut self, address: u16, value: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    /// Level of the cartridge's IRQ line, which is wired to the CPU's
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Called by the PPU at dot 260 of every visible and pre-render scanline while rendering is enabled
+    fn notify_scanline(&mut self) {}
+
+    /// Called once per CPU cycle
+    fn notify_cpu_cycle(&mut self) {}
+
+    /// The whole PRG RAM of the board, which is persisted when it is battery backed
+    fn prg_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// Memory that outlives power off, which is the battery-backed PRG RAM unless the board has a writable disk
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram().map(<[u8]>::to_vec)
+    }
+
+    /// Restores what `save_data` returned in an earlier session
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(prg_ram) = self.prg_ram_mut() {
+            let length = prg_ram.len().min(data.len());
+            prg_ram[..length].copy_from_slice(&data[..length]);
+        }
+    }
+
+    /// Ejects the disk and inserts its next side, for boards with a disk drive
+    fn switch_disk_side(&mut self) {}
+}
+
+/// Builds the mapper for the iNES mapper number of `rom`, with the trainer of the ROM copied to $7000-$71FF
+pub fn from_rom(rom: &ROM) -> Result<Box<dyn Mapper>, ROMError> {
+    let mut mapper = board(rom)?;
+    match (rom.trainer(), mapper.prg_ram_mut()) {
+        (Some(trainer), Some(prg_ram)) => prg_ram[0x1000..0x1000 + TRAINER_SIZE].copy_from_slice(trainer),
+        (Some(_), None) => return Err(ROMError::UnsupportedTrainer(rom.mapper_number())),
+        (None, _) => (),
+    }
+    Ok(mapper)
+}
+
+fn board(rom: &ROM) -> Result<Box<dyn Mapper>, ROMError> {
+    // NES 2.0 lists battery-backed PRG RAM separately, but mappers map both the same way
+    let prg_ram_size = match (rom.prg_ram_size() + rom.prg_nvram_size(), rom.has_trainer()) {
+        (prg_ram_size, false) => prg_ram_size,
+        // The trainer needs the whole $6000-$7FFF range, even when a NES 2.0 header declares less PRG RAM
+        (prg_ram_size, true) => prg_ram_size.max(PRG_RAM_PAGE_SIZE),
+    };
+    let chr_ram_size = rom.chr_ram_size() + rom.chr_nvram_size();
+    match rom.mapper_number() {
+        0 => Ok(Box::new(NROM::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            prg_ram_size,
+            rom.mirroring(),
+        ))),
+        1 => Ok(Box::new(MMC1::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            prg_ram_size,
+        ))),
+        2 => Ok(Box::new(UxROM::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            rom.mirroring(),
+            bus_conflicts(rom.submapper_number(), true),
+        ))),
+        3 => Ok(Box::new(CNROM::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            rom.mirroring(),
+            bus_conflicts(rom.submapper_number(), true),
+        ))),
+        4 => Ok(Box::new(MMC3::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            prg_ram_size,
+            rom.mirroring(),
+            match rom.submapper_number() {
+                4 => IRQRevision::Old,
+                _ => IRQRevision::New,
+            },
+        ))),
+        7 => Ok(Box::new(AxROM::new(
+            rom.prg_rom().to_vec(),
+            rom.chr_rom().to_vec(),
+            chr_ram_size,
+            bus_conflicts(rom.submapper_number(), false),
+        ))),
+        mapper_number => Err(ROMError::UnsupportedMapper(mapper_number)),
+    }
+}
+
+/// NES 2.0 submappers 1 and 2 of the discrete logic mappers tell whether the board has bus conflicts.
+/// Most UxROM and CNROM boards have them, while the AOROM variant of AxROM doesn't
+fn bus_conflicts(submapper_number: u8, default: bool) -> bool {
+    match submapper_number {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
+}
+
+/// On boards with bus conflicts, the ROM also drives the data bus during register writes, and the bits it holds
+/// low win over the ones written by the CPU. Games avoid them by writing to a ROM byte holding the same value
+fn bus_conflict(value: u8, rom_byte: u8) -> u8 {
+    value & rom_byte
+}
+
+/// Builds the mapper for the board name of a UNIF file
+pub fn from_unif(unif: &UNIF) -> Result<Box<dyn Mapper>, ROMError> {
+    let prg_rom = unif.prg_rom().to_vec();
+    let chr_rom = unif.chr_rom().to_vec();
+    let mirroring = unif.mirroring().unwrap_or_default();
+    // UNIF files don't tell the CHR RAM size, so boards without CHR ROM get the usual 8 KiB
+    let chr_ram_size = CRH_PAGE_SIZE;
+    match unif.board() {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => Ok(Box::new(NROM::new(
+            prg_rom,
+            chr_rom,
+            chr_ram_size,
+            PRG_RAM_PAGE_SIZE,
+            mirroring,
+        ))),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM" | "SLROM"
+        | "SL1ROM" | "SNROM" | "SUROM" => Ok(Box::new(MMC1::new(prg_rom, chr_rom, chr_ram_size, PRG_RAM_PAGE_SIZE))),
+        "SOROM" => Ok(Box::new(MMC1::new(
+            prg_rom,
+            chr_rom,
+            chr_ram_size,
+            2 * PRG_RAM_PAGE_SIZE,
+        ))),
+        "SXROM" => Ok(Box::new(MMC1::new(
+            prg_rom,
+            chr_rom,
+            chr_ram_size,
+            4 * PRG_RAM_PAGE_SIZE,
+        ))),
+        "UNROM" | "UOROM" => Ok(Box::new(UxROM::new(prg_rom, chr_rom, chr_ram_size, mirroring, true))),
+        "CNROM" => Ok(Box::new(CNROM::new(prg_rom, chr_rom, chr_ram_size, mirroring, true))),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TL1ROM" | "TR1ROM" | "TSROM" | "TVROM" => {
+            Ok(Box::new(MMC3::new(
+                prg_rom,
+                chr_rom,
+                chr_ram_size,
+                PRG_RAM_PAGE_SIZE,
+                mirroring,
+                IRQRevision::New,
+            )))
+        }
+        "AMROM" => Ok(Box::new(AxROM::new(prg_rom, chr_rom, chr_ram_size, true))),
+        "ANROM" | "AN1ROM" | "AOROM" => Ok(Box::new(AxROM::new(prg_rom, chr_rom, chr_ram_size, false))),
+        board => Err(ROMError::UnsupportedBoard(board.to_string())),
+    }
+}
+
+/// Builds the RAM adapter of the Famicom Disk System with `disk` inserted
+pub fn from_disk(bios: Vec<u8>, disk: &Disk) -> Result<Box<dyn Mapper>, ROMError> {
+    match bios.len() {
+        fds::BIOS_SIZE => Ok(Box::new(FDS::new(bios, disk))),
+        _ => Err(ROMError::InvalidBIOS),
+    }
+}
+
+/// Memory split in equally sized banks that a mapper switches in and out of the address space
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Banks {
+    memory: Vec<u8>,
+    bank_size: usize,
+}
+
+impl Banks {
+    pub fn new(memory: Vec<u8>, bank_size: usize) -> Self {
+        Self { memory, bank_size }
+    }
+
+    pub fn count(&self) -> usize {
+        (self.memory.len() / self.bank_size).max(1)
+    }
+
+    pub fn last(&self) -> usize {
+        self.count() - 1
+    }
+
+    /// Bank numbers wrap around the bank count, as boards don't decode the unused upper bits
+    pub fn read(&self, bank: usize, offset: u16) -> u8 {
+        self.index(bank, offset).map_or(0, |index| self.memory[index])
+    }
+
+    pub fn write(&mut self, bank: usize, offset: u16, value: u8) {
+        if let Some(index) = self.index(bank, offset) {
+            self.memory[index] = value;
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    fn index(&self, bank: usize, offset: u16) -> Option<usize> {
+        match self.memory.len() {
+            0 => None,
+            // Memories smaller than a bank are mirrored across it
+            length => Some(((bank % self.count()) * self.bank_size + offset as usize % self.bank_size) % length),
+        }
+    }
+}
+
+/// Pattern table memory of the cartridge. Boards without CHR ROM have CHR RAM instead, filled through $2007
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct CHR {
+    banks: Banks,
+    ram: bool,
+}
+
+impl CHR {
+    /// `chr_ram_size` only matters without CHR ROM, and a size of 0 gives the usual 8 KiB
+    pub fn new(chr_rom: Vec<u8>, chr_ram_size: usize, bank_size: usize) -> Self {
+        match (chr_rom.is_empty(), chr_ram_size) {
+            (false, _) => Self {
+                banks: Banks::new(chr_rom, bank_size),
+                ram: false,
+            },
+            (true, 0) => Self {
+                banks: Banks::new(vec![0; CRH_PAGE_SIZE], bank_size),
+                ram: true,
+            },
+            (true, chr_ram_size) => Self {
+                banks: Banks::new(vec![0; chr_ram_size], bank_size),
+                ram: true,
+            },
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.banks.count()
+    }
+
+    pub fn read(&self, bank: usize, offset: u16) -> u8 {
+        self.banks.read(bank, offset)
+    }
+
+    /// Writes to CHR ROM are ignored
+    pub fn write(&mut self, bank: usize, offset: u16, value: u8) {
+        if self.ram {
+            self.banks.write(bank, offset, value);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{from_rom, from_unif, Banks, CHR};
+    use crate::{
+        error::ROMError,
+        rom::{unif::UNIF, PRG_PAGE_SIZE, ROM, TRAINER_SIZE},
+    };
+
+    /// ROM of `bank_count` banks filled with $FF, whose first byte is the index of the bank
+    pub fn numbered_banks(bank_size: usize, bank_count: usize) -> Vec<u8> {
+        let mut rom = vec![0xFF; bank_size * bank_count];
+        for (index, bank) in rom.chunks_exact_mut(bank_size).enumerate() {
+            bank[0] = index as u8;
+        }
+        rom
+    }
+
+    #[test]
+    pub fn test_banks() {
+        let banks = Banks::new(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05], 2);
+        assert_eq!(banks.count(), 3);
+        assert_eq!(banks.last(), 2);
+        assert_eq!(banks.read(1, 0x0001), 0x03);
+        assert_eq!(banks.read(4, 0x0002), 0x02);
+
+        let mut banks = Banks::new(vec![0x00; 2], 4);
+        banks.write(0, 0x0003, 0xAA);
+        assert_eq!(banks.read(0, 0x0001), 0xAA);
+
+        let banks = Banks::new(vec![], 4);
+        assert_eq!(banks.read(0, 0x0001), 0x00);
+    }
+
+    #[test]
+    pub fn test_chr() {
+        let mut chr = CHR::new(vec![0x01; 0x2000], 0, 0x1000);
+        chr.write(1, 0x0010, 0x02);
+        assert_eq!(chr.read(1, 0x0010), 0x01);
+
+        let mut chr = CHR::new(vec![], 0, 0x1000);
+        assert_eq!(chr.count(), 2);
+        chr.write(1, 0x0010, 0x02);
+        assert_eq!(chr.read(1, 0x0010), 0x02);
+        assert_eq!(chr.read(0, 0x0010), 0x00);
+
+        let chr = CHR::new(vec![], 0x8000, 0x1000);
+        assert_eq!(chr.count(), 8);
+    }
+
+    #[test]
+    pub fn test_nes_2_0_chr_ram() {
+        // CNROM without bus conflicts and with 32 KiB of CHR RAM
+        let mut content = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x30, 0x08, 0x10, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00,
+        ];
+        content.extend_from_slice(&[0x00; 2 * PRG_PAGE_SIZE]);
+        let mut mapper = from_rom(&ROM::with_content(content).unwrap()).unwrap();
+        for bank in 0..4 {
+            mapper.cpu_write(0x8000, bank);
+            mapper.ppu_write(0x0000, bank + 1);
+        }
+        for bank in 0..4 {
+            mapper.cpu_write(0x8000, bank);
+            assert_eq!(mapper.ppu_read(0x0000), bank + 1);
+        }
+    }
+
+    #[test]
+    pub fn test_trainer() {
+        let mut content = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        content.extend_from_slice(&[0xAA; TRAINER_SIZE]);
+        content.extend_from_slice(&[0x00; PRG_PAGE_SIZE]);
+        let mut mapper = from_rom(&ROM::with_content(content.clone()).unwrap()).unwrap();
+        assert_eq!(mapper.cpu_read(0x6FFF), Some(0x00));
+        assert_eq!(mapper.cpu_read(0x7000), Some(0xAA));
+        assert_eq!(mapper.cpu_read(0x71FF), Some(0xAA));
+        assert_eq!(mapper.cpu_read(0x7200), Some(0x00));
+
+        // A NES 2.0 header without PRG RAM still gets room for the trainer
+        content[7] = 0x08;
+        let mut mapper = from_rom(&ROM::with_content(content.clone()).unwrap()).unwrap();
+        assert_eq!(mapper.cpu_read(0x7000), Some(0xAA));
+
+        // UxROM has no PRG RAM at all
+        content[6] = 0x24;
+        assert_eq!(
+            from_rom(&ROM::with_content(content).unwrap()).err(),
+            Some(ROMError::UnsupportedTrainer(2))
+        );
+    }
+
+    #[test]
+    pub fn test_from_unif() {
+        let unif = |board: &str| {
+            let mut content = b"UNIF".to_vec();
+            content.resize(0x20, 0x00);
+            content.extend(b"MAPR");
+            content.extend(&(board.len() as u32).to_le_bytes());
+            content.extend(board.as_bytes());
+            content.extend(b"PRG0\x00\x40\x00\x00");
+            content.extend(vec![0xEA; PRG_PAGE_SIZE]);
+            UNIF::with_content(&content).unwrap()
+        };
+        let mut mapper = from_unif(&unif("NES-NROM-128")).unwrap();
+        assert_eq!(mapper.cpu_read(0xC000), Some(0xEA));
+        assert!(from_unif(&unif("NES-SLROM")).is_ok());
+        assert_eq!(
+            from_unif(&unif("UNL-Sachen-8259A")).err(),
+            Some(ROMError::UnsupportedBoard("Sachen-8259A".to_string()))
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use super::{Banks, Mapper, CHR};
+use crate::rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE, PRG_RAM_PAGE_SIZE};
+
+/// Mapper 0. 16 or 32 KiB of PRG ROM and 8 KiB of CHR without any bank switching
+pub struct NROM {
+    prg_rom: Banks,
+    chr: CHR,
+    prg_ram: Banks,
+    mirroring: Mirroring,
+}
+
+impl NROM {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        chr_ram_size: usize,
+        prg_ram_size: usize,
+        mirroring: Mirroring,
+    ) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_PAGE_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CRH_PAGE_SIZE),
+            prg_ram: Banks::new(vec![0; prg_ram_size], PRG_RAM_PAGE_SIZE),
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for NROM {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => Some(self.prg_ram.read(0, address - 0x6000)),
+            0x8000..=0xBFFF => Some(self.prg_rom.read(0, address - 0x8000)),
+            // NROM-128 mirrors its only bank here
+            0xC000..=0xFFFF => Some(self.prg_rom.read(self.prg_rom.last(), address - 0xC000)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram.write(0, address - 0x6000, value);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(0, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(self.prg_ram.memory())
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.memory_mut())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::NROM;
+    use crate::{
+        hardware::mapper::Mapper,
+        rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE, PRG_RAM_PAGE_SIZE},
+    };
+
+    #[test]
+    pub fn test_nrom_mapper() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0] = 0x01;
+        prg_rom[PRG_PAGE_SIZE] = 0x02;
+        let mut chr_rom = vec![0u8; CRH_PAGE_SIZE];
+        chr_rom[0x1000] = 0x03;
+        let mut mapper = NROM::new(prg_rom, chr_rom, 0, PRG_RAM_PAGE_SIZE, Mirroring::Vertical);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x01));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x02));
+        assert_eq!(mapper.cpu_read(0x5000), None);
+        assert_eq!(mapper.ppu_read(0x1000), 0x03);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        mapper.cpu_write(0x6010, 0x04);
+        mapper.cpu_write(0x8000, 0x05);
+        assert_eq!(mapper.cpu_read(0x6010), Some(0x04));
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x01));
+    }
+
+    #[test]
+    pub fn test_nrom_128_mirroring() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE];
+        prg_rom[0x0010] = 0x01;
+        let mut mapper = NROM::new(prg_rom, vec![], 0, 0, Mirroring::Horizontal);
+        assert_eq!(mapper.cpu_read(0x8010), Some(0x01));
+        assert_eq!(mapper.cpu_read(0xC010), Some(0x01));
+    }
+}
@@ -0,0 +1,125 @@
+use super::{Banks, Mapper, CHR};
+use crate::rom::{nsf::NSF, Mirroring, CRH_PAGE_SIZE, PRG_RAM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 4 * 1024;
+/// Music that isn't bank switched sees its data as it would be in a 32 KiB NROM
+const FIXED_BANKS: [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Synthetic board that NSF music runs on. It has 8 KiB of RAM at $6000-$7FFF, and eight 4 KiB PRG banks
+/// at $8000-$FFFF switched by writing bank numbers to $5FF8-$5FFF
+pub struct NSFCartridge {
+    prg_rom: Banks,
+    chr: CHR,
+    prg_ram: Banks,
+    banks: [u8; 8],
+    bank_switching: bool,
+}
+
+impl NSFCartridge {
+    pub fn new(nsf: &NSF) -> Self {
+        // Bank switched data starts at the offset of the load address within its bank
+        let (padding, banks) = match nsf.bank_switching() {
+            Some(banks) => ((nsf.load_address() & 0x0FFF) as usize, banks),
+            None => (nsf.load_address().saturating_sub(0x8000) as usize, FIXED_BANKS),
+        };
+        let mut prg_rom = vec![0; padding];
+        prg_rom.extend_from_slice(nsf.data());
+        // Unused space reads as 0 instead of mirroring the data
+        let length = match nsf.bank_switching() {
+            Some(_) => prg_rom.len().div_ceil(PRG_BANK_SIZE) * PRG_BANK_SIZE,
+            None => prg_rom.len().max(FIXED_BANKS.len() * PRG_BANK_SIZE),
+        };
+        prg_rom.resize(length, 0);
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_BANK_SIZE),
+            chr: CHR::new(vec![], CRH_PAGE_SIZE, CRH_PAGE_SIZE),
+            prg_ram: Banks::new(vec![0; PRG_RAM_PAGE_SIZE], PRG_RAM_PAGE_SIZE),
+            banks,
+            bank_switching: nsf.bank_switching().is_some(),
+        }
+    }
+}
+
+impl Mapper for NSFCartridge {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => Some(self.prg_ram.read(0, address - 0x6000)),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(address as usize - 0x8000) / PRG_BANK_SIZE];
+                Some(self.prg_rom.read(bank as usize, address & 0x0FFF))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5FF8..=0x5FFF if self.bank_switching => self.banks[address as usize - 0x5FF8] = value,
+            0x6000..=0x7FFF => self.prg_ram.write(0, address - 0x6000, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(0, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(self.prg_ram.memory())
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.memory_mut())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::NSFCartridge;
+    use crate::{hardware::mapper::Mapper, rom::nsf::NSF};
+
+    fn nsf(load_address: u16, banks: [u8; 8], data: &[u8]) -> NSF {
+        let mut content = b"NESM\x1A\x01\x01\x01".to_vec();
+        content.extend(&load_address.to_le_bytes());
+        content.resize(0x70, 0x00);
+        content.extend(&banks);
+        content.resize(0x80, 0x00);
+        content.extend(data);
+        NSF::with_content(&content).unwrap()
+    }
+
+    #[test]
+    pub fn test_nsf_cartridge() {
+        let mut cartridge = NSFCartridge::new(&nsf(0xC000, [0; 8], &[0x01, 0x02]));
+        assert_eq!(cartridge.cpu_read(0x8000), Some(0x00));
+        assert_eq!(cartridge.cpu_read(0xC001), Some(0x02));
+        cartridge.cpu_write(0x5FF8, 0x04);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(0x00));
+
+        cartridge.cpu_write(0x6010, 0x03);
+        assert_eq!(cartridge.cpu_read(0x6010), Some(0x03));
+        assert_eq!(cartridge.cpu_read(0x5FF8), None);
+    }
+
+    #[test]
+    pub fn test_nsf_bank_switching() {
+        let mut data = vec![0x01; 0x1000];
+        data.extend(vec![0x02; 0x1000]);
+        let mut cartridge = NSFCartridge::new(&nsf(0x8100, [0, 0, 0, 0, 0, 0, 0, 1], &data));
+        assert_eq!(cartridge.cpu_read(0x80FF), Some(0x00));
+        assert_eq!(cartridge.cpu_read(0x8100), Some(0x01));
+        assert_eq!(cartridge.cpu_read(0xF100), Some(0x02));
+
+        cartridge.cpu_write(0x5FF8, 0x02);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(0x02));
+        assert_eq!(cartridge.cpu_read(0x8100), Some(0x00));
+    }
+}
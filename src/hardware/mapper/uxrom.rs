@@ -0,0 +1,92 @@
+use super::{bus_conflict, Banks, Mapper, CHR};
+use crate::rom::{Mirroring, CRH_PAGE_SIZE, PRG_PAGE_SIZE};
+
+/// Mapper 2. A switchable 16 KiB PRG bank at $8000 and the last one fixed at $C000
+pub struct UxROM {
+    prg_rom: Banks,
+    chr: CHR,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+
+impl UxROM {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Self {
+        Self {
+            prg_rom: Banks::new(prg_rom, PRG_PAGE_SIZE),
+            chr: CHR::new(chr_rom, chr_ram_size, CRH_PAGE_SIZE),
+            mirroring,
+            bus_conflicts,
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for UxROM {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xBFFF => Some(self.prg_rom.read(self.prg_bank as usize, address - 0x8000)),
+            0xC000..=0xFFFF => Some(self.prg_rom.read(self.prg_rom.last(), address - 0xC000)),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xFFFF = address {
+            self.prg_bank = match self.bus_conflicts {
+                true => bus_conflict(value, self.cpu_read(address).unwrap_or(0xFF)),
+                false => value,
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr.write(0, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::UxROM;
+    use crate::{
+        hardware::mapper::{tests::numbered_banks, Mapper},
+        rom::{Mirroring, PRG_PAGE_SIZE},
+    };
+
+    #[test]
+    pub fn test_uxrom_mapper() {
+        let mut mapper = UxROM::new(numbered_banks(PRG_PAGE_SIZE, 8), vec![], 0, Mirroring::Vertical, false);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x00));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x07));
+
+        mapper.cpu_write(0x8000, 0x03);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x03));
+        assert_eq!(mapper.cpu_read(0xC000), Some(0x07));
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    pub fn test_uxrom_bus_conflicts() {
+        let mut mapper = UxROM::new(numbered_banks(PRG_PAGE_SIZE, 8), vec![], 0, Mirroring::Vertical, true);
+        // $C000 holds the index of the last bank, 7
+        mapper.cpu_write(0xC000, 0x06);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x06));
+
+        mapper.cpu_write(0xC000, 0x0D);
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x05));
+    }
+}
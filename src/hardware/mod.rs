@@ -1,3 +1,5 @@
+pub mod apu;
 pub mod cpu;
+pub mod mapper;
 pub mod memory;
 pub mod ppu;
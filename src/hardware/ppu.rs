@@ -1,53 +1,404 @@
-use super::memory::Memory;
+use super::{mapper::Mapper, memory::Memory};
+use crate::rom::Mirroring;
 
 use log::debug;
 
 pub const INTERNAL_MEMORY_SIZE: usize = 2 * 1024;
 pub const OAM_SIZE: usize = 256;
-pub const PATTERN_TABLE_SECTION_SIZE: usize = 4 * 1024;
+pub const PALETTE_RAM_SIZE: usize = 32;
 pub const NAME_TABLE_SIZE: usize = 1024;
-pub const TILE_SIZE: u32 = 8;
 pub const PATTERN_TILE_SIZE: usize = 16;
+pub const PPU_DOTS_PER_CPU_CYCLE: u32 = 3;
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+pub const SPRITES_PER_SCANLINE: usize = 8;
+
+const PRE_RENDER_SCANLINE: u32 = 261;
+const VBLANK_SCANLINE: u32 = 241;
 
 #[derive(Copy, Clone)]
 pub struct PPU {
     pub registers: Registers,
+    pub internal_registers: InternalRegisters,
     pub clock: Clock,
     pub internal_memory: [u8; INTERNAL_MEMORY_SIZE],
+    /// Additional name table memory provided by four-screen cartridges
+    pub four_screen_memory: [u8; INTERNAL_MEMORY_SIZE],
+    pub palette_ram: [u8; PALETTE_RAM_SIZE],
     pub oam: OAM,
+    /// Palette indices of the last rendered frame
+    pub framebuffer: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    background: Background,
+    sprites: Sprites,
 }
 
 impl PPU {
     pub fn new() -> Self {
         Self {
             registers: Default::default(),
+            internal_registers: Default::default(),
             clock: Default::default(),
             internal_memory: [0u8; INTERNAL_MEMORY_SIZE],
+            four_screen_memory: [0u8; INTERNAL_MEMORY_SIZE],
+            palette_ram: [0u8; PALETTE_RAM_SIZE],
             oam: OAM::new(),
+            framebuffer: [[0u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            background: Default::default(),
+            sprites: Default::default(),
         }
     }
 
-    pub fn state(&self) -> Option<State> {
-        match (self.clock.scanline, self.clock.cycle) {
-            (scanline @ 0..=239, cycle @ 1..=256) if scanline % TILE_SIZE == 0 && cycle % TILE_SIZE == 1 => {
-                Some(State::RenderTile {
-                    x: (cycle - 1),
-                    y: scanline,
-                })
+    /// Advances the PPU by one dot and does the work of that dot
+    pub fn step(&mut self, mut mapper: Option<&mut dyn Mapper>) -> Option<State> {
+        let rendering_enabled = self.registers.mask_flags().rendering_enabled();
+
+        self.clock.step();
+        // On odd frames the last dot of the pre-render scanline is skipped while rendering
+        if self.clock.scanline == PRE_RENDER_SCANLINE
+            && self.clock.cycle == 340
+            && self.clock.odd_frame
+            && rendering_enabled
+        {
+            self.clock.step();
+        }
+
+        let Clock { scanline, cycle, .. } = self.clock;
+        let state = match (scanline, cycle) {
+            (VBLANK_SCANLINE, 1) => {
+                self.registers.set_status_flags(StatusFlags {
+                    vblank: true,
+                    ..self.registers.status_flags()
+                });
+                Some(State::VBlankToggle(true))
+            }
+            (PRE_RENDER_SCANLINE, 1) => {
+                self.registers.set_status_flags(StatusFlags {
+                    vblank: false,
+                    sprite_0_hit: false,
+                    sprite_overflow: false,
+                    ..self.registers.status_flags()
+                });
+                Some(State::VBlankToggle(false))
             }
-            (241, 1) => Some(State::VBlankToggle(true)),
-            (261, 1) => Some(State::VBlankToggle(false)),
             _ => None,
+        };
+
+        let visible_scanline = (scanline as usize) < SCREEN_HEIGHT;
+        if rendering_enabled && (visible_scanline || scanline == PRE_RENDER_SCANLINE) {
+            self.fetch_background(mapper.as_deref_mut());
+            self.fetch_sprites(mapper.as_deref_mut());
+            if cycle == 260 {
+                if let Some(mapper) = mapper.as_deref_mut() {
+                    mapper.notify_scanline();
+                }
+            }
+        }
+        if visible_scanline && (1..=SCREEN_WIDTH as u32).contains(&cycle) {
+            self.render_pixel(mapper);
+        }
+
+        state
+    }
+
+    fn fetch_background(&mut self, mapper: Option<&mut dyn Mapper>) {
+        let Clock { scanline, cycle, .. } = self.clock;
+        if let 2..=257 | 321..=337 = cycle {
+            self.background.shift();
+
+            let v = self.internal_registers.v;
+            match (cycle - 1) % 8 {
+                0 => {
+                    self.background.load_shifters();
+                    self.background.name_table_byte = self.read_memory(0x2000 | (v & 0x0FFF), mapper);
+                }
+                2 => {
+                    let address = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    let attribute = self.read_memory(address, mapper);
+                    // Every attribute byte covers 4x4 tiles, with 2 bits for each 2x2 tile quadrant
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.background.attribute_bits = (attribute >> shift) & 0b0000_0011;
+                }
+                4 => self.background.pattern_low = self.read_memory(self.background_pattern_address(), mapper),
+                6 => self.background.pattern_high = self.read_memory(self.background_pattern_address() + 8, mapper),
+                7 => self.internal_registers.increment_coarse_x(),
+                _ => (),
+            }
+        }
+
+        match cycle {
+            256 => self.internal_registers.increment_y(),
+            257 => self.internal_registers.copy_horizontal_position(),
+            280..=304 if scanline == PRE_RENDER_SCANLINE => self.internal_registers.copy_vertical_position(),
+            _ => (),
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let fine_y = (self.internal_registers.v >> 12) & 0x07;
+        self.registers.control_flags().background_pattern_table_address
+            + self.background.name_table_byte as u16 * PATTERN_TILE_SIZE as u16
+            + fine_y
+    }
+
+    fn fetch_sprites(&mut self, mapper: Option<&mut dyn Mapper>) {
+        let Clock { scanline, cycle, .. } = self.clock;
+        match cycle {
+            257 => {
+                // Sprites are never drawn on the first scanline, as there is no evaluation on the pre-render one
+                match scanline {
+                    PRE_RENDER_SCANLINE => self.sprites.secondary_oam_count = 0,
+                    _ => self.evaluate_sprites(),
+                }
+                self.registers.oamaddr = 0;
+                self.sprite_fetch(mapper);
+            }
+            258..=320 => {
+                self.registers.oamaddr = 0;
+                self.sprite_fetch(mapper);
+            }
+            _ => (),
+        }
+    }
+
+    /// Looks for the sprites visible on the next scanline and copies them to secondary OAM
+    fn evaluate_sprites(&mut self) {
+        let scanline = self.clock.scanline;
+        let sprite_height = self.registers.control_flags().sprite_height;
+
+        let in_range = |y: u8| scanline.wrapping_sub(y as u32) < sprite_height;
+
+        self.sprites.secondary_oam_count = 0;
+        let mut index = 0;
+        while index < 64 && self.sprites.secondary_oam_count < SPRITES_PER_SCANLINE {
+            let entry = &self.oam.0[index * 4..index * 4 + 4];
+            if in_range(entry[0]) {
+                self.sprites.secondary_oam[self.sprites.secondary_oam_count] =
+                    Sprite::from_oam_entry(entry, index == 0);
+                self.sprites.secondary_oam_count += 1;
+            }
+            index += 1;
+        }
+
+        // Once 8 sprites are found, the hardware keeps looking for a 9th one but wrongly increments the byte offset
+        // along with the sprite index, reading tile indexes, attributes and X positions as Y coordinates
+        let mut offset = 0;
+        while index < 64 {
+            if in_range(self.oam.0[index * 4 + offset]) {
+                self.registers.set_status_flags(StatusFlags {
+                    sprite_overflow: true,
+                    ..self.registers.status_flags()
+                });
+                break;
+            }
+            index += 1;
+            offset = (offset + 1) % 4;
+        }
+    }
+
+    /// Fetches the pattern of one sprite of secondary OAM every 8 dots between dots 257 and 320
+    fn sprite_fetch(&mut self, mapper: Option<&mut dyn Mapper>) {
+        let slot = (self.clock.cycle - 257) as usize / 8;
+        // Empty slots still fetch tile $FF, which is observable by mappers watching the PPU's address bus
+        let sprite = match slot < self.sprites.secondary_oam_count {
+            true => self.sprites.secondary_oam[slot],
+            false => Sprite {
+                y: 0xFF,
+                tile_index: 0xFF,
+                attributes: 0xFF,
+                x: 0xFF,
+                ..Default::default()
+            },
+        };
+
+        match (self.clock.cycle - 257) % 8 {
+            0 => self.sprites.scanline[slot] = sprite,
+            4 => {
+                let pattern = self.read_memory(self.sprite_pattern_address(&sprite), mapper);
+                self.sprites.scanline[slot].pattern_low = sprite.flip_pattern(pattern);
+            }
+            6 => {
+                let pattern = self.read_memory(self.sprite_pattern_address(&sprite) + 8, mapper);
+                self.sprites.scanline[slot].pattern_high = sprite.flip_pattern(pattern);
+            }
+            7 if slot == SPRITES_PER_SCANLINE - 1 => self.sprites.scanline_count = self.sprites.secondary_oam_count,
+            _ => (),
+        }
+    }
+
+    fn sprite_pattern_address(&self, sprite: &Sprite) -> u16 {
+        let control_flags = self.registers.control_flags();
+        let sprite_height = control_flags.sprite_height;
+        let row = match self.clock.scanline.wrapping_sub(sprite.y as u32) {
+            row if row >= sprite_height => 0,
+            row if sprite.flip_vertically() => sprite_height - 1 - row,
+            row => row,
+        } as u16;
+
+        match sprite_height {
+            // 8x16 sprites select the pattern table by bit 0 of the tile index and use 2 consecutive tiles
+            16 => {
+                let pattern_table_address = (sprite.tile_index as u16 & 0x01) * 0x1000;
+                let tile_index = (sprite.tile_index & 0xFE) as u16 + row / 8;
+                pattern_table_address + tile_index * PATTERN_TILE_SIZE as u16 + row % 8
+            }
+            _ => control_flags.sprite_pattern_table_address + sprite.tile_index as u16 * PATTERN_TILE_SIZE as u16 + row,
+        }
+    }
+
+    fn render_pixel(&mut self, mapper: Option<&mut dyn Mapper>) {
+        let x = self.clock.cycle as usize - 1;
+        let y = self.clock.scanline as usize;
+        let mask_flags = self.registers.mask_flags();
+
+        let (pixel, palette) = match mask_flags.show_background && (x >= 8 || mask_flags.show_background_left) {
+            true => self.background.pixel(self.internal_registers.x),
+            false => (0, 0),
+        };
+        let sprite = match mask_flags.show_sprites && (x >= 8 || mask_flags.show_sprites_left) {
+            true => self.sprites.pixel(x as u8),
+            false => None,
+        };
+
+        // Sprite 0 is always the first sprite of the scanline, so its opaque pixels are never hidden by other sprites
+        if let Some((_, Sprite { sprite_0: true, .. })) = sprite {
+            if pixel != 0 && x != 255 {
+                self.registers.set_status_flags(StatusFlags {
+                    sprite_0_hit: true,
+                    ..self.registers.status_flags()
+                });
+            }
+        }
+
+        // Transparent pixels show the universal background color at $3F00
+        let palette_address = match (pixel, sprite) {
+            (0, None) => 0x3F00,
+            (0, Some((sprite_pixel, sprite))) => sprite.palette_address(sprite_pixel),
+            (_, Some((sprite_pixel, sprite))) if !sprite.behind_background() => sprite.palette_address(sprite_pixel),
+            _ => 0x3F00 | ((palette as u16) << 2) | pixel as u16,
+        };
+
+        let color = self.read_memory(palette_address, mapper);
+        self.framebuffer[y][x] = match mask_flags.grayscale {
+            true => color & 0b0011_0000,
+            false => color,
+        };
+    }
+
+    fn read_memory(&mut self, address: u16, mapper: Option<&mut dyn Mapper>) -> u8 {
+        MMU::new(self, mapper).read(address).unwrap_or(0)
+    }
+
+    /// Reads the register mapped to `address` ($2000-$3FFF, mirrored every 8 bytes) from the CPU's side
+    pub fn read_register(&mut self, address: u16, mapper: Option<&mut dyn Mapper>) -> u8 {
+        let value = match address % 0x08 {
+            2 => {
+                // The unused low bits of PPUSTATUS return the stale value left on the PPU's data bus
+                let status_flags = self.registers.status_flags();
+                let value = StatusFlags {
+                    least_significant_bits: self.registers.io_latch & 0b0001_1111,
+                    ..status_flags
+                }
+                .into();
+                self.registers.set_status_flags(StatusFlags {
+                    vblank: false,
+                    ..status_flags
+                });
+                self.internal_registers.w = false;
+                value
+            }
+            4 => self.oam.0[self.registers.oamaddr as usize],
+            7 => {
+                let address = self.internal_registers.v & 0x3FFF;
+                let value = match address {
+                    // Palette reads are not buffered, but the buffer is still filled with the name table byte that
+                    // sits "underneath" the palette
+                    0x3F00..=0x3FFF => {
+                        let mut mmu = MMU::new(self, mapper);
+                        let value = mmu.read(address).unwrap_or(0);
+                        let buffered = mmu.read(address - 0x1000).unwrap_or(0);
+                        self.registers.ppudata = buffered;
+                        value
+                    }
+                    _ => {
+                        let buffered = self.registers.ppudata;
+                        self.registers.ppudata = MMU::new(self, mapper).read(address).unwrap_or(0);
+                        buffered
+                    }
+                };
+                self.increment_vram_address();
+                value
+            }
+            // Write only registers
+            _ => self.registers.io_latch,
+        };
+        self.registers.io_latch = value;
+        value
+    }
+
+    /// Writes the register mapped to `address` ($2000-$3FFF, mirrored every 8 bytes) from the CPU's side
+    pub fn write_register(&mut self, address: u16, value: u8, mapper: Option<&mut dyn Mapper>) {
+        self.registers.io_latch = value;
+        let internal_registers = &mut self.internal_registers;
+        match address % 0x08 {
+            0 => {
+                self.registers.ppuctrl = value;
+                internal_registers.t = (internal_registers.t & !0x0C00) | (((value & 0b0000_0011) as u16) << 10);
+            }
+            1 => self.registers.ppumask = value,
+            2 => (),
+            3 => self.registers.oamaddr = value,
+            4 => {
+                self.oam.0[self.registers.oamaddr as usize] = value;
+                self.registers.oamaddr = self.registers.oamaddr.wrapping_add(1);
+            }
+            5 => {
+                match internal_registers.w {
+                    false => {
+                        internal_registers.t = (internal_registers.t & !0x001F) | (value >> 3) as u16;
+                        internal_registers.x = value & 0b0000_0111;
+                    }
+                    true => {
+                        internal_registers.t = (internal_registers.t & !0x73E0)
+                            | (((value & 0b0000_0111) as u16) << 12)
+                            | (((value & 0b1111_1000) as u16) << 2);
+                    }
+                }
+                internal_registers.w = !internal_registers.w;
+            }
+            6 => {
+                match internal_registers.w {
+                    false => {
+                        internal_registers.t = (internal_registers.t & 0x00FF) | (((value & 0b0011_1111) as u16) << 8);
+                    }
+                    true => {
+                        internal_registers.t = (internal_registers.t & 0xFF00) | value as u16;
+                        internal_registers.v = internal_registers.t;
+                    }
+                }
+                internal_registers.w = !internal_registers.w;
+            }
+            7 => {
+                let address = internal_registers.v & 0x3FFF;
+                MMU::new(self, mapper).write(address, value);
+                self.increment_vram_address();
+            }
+            _ => unreachable!(),
         }
     }
+
+    fn increment_vram_address(&mut self) {
+        let increment = self.registers.control_flags().vram_address_increment;
+        self.internal_registers.v = self.internal_registers.v.wrapping_add(increment) & 0x7FFF;
+    }
+
+    /// The PPU pulls the CPU's NMI line while it's in vblank and NMI generation is enabled in PPUCTRL
+    pub fn nmi_line(&self) -> bool {
+        self.registers.status_flags().vblank && self.registers.control_flags().nmi_enabled
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum State {
-    RenderTile {
-        x: u32,
-        y: u32,
-    },
     VBlankToggle(bool),
 }
 
@@ -57,14 +408,22 @@ pub struct Registers {
     pub ppumask: u8,
     pub ppustatus: u8,
     pub oamaddr: u8,
-    pub oamdata: u8,
-    pub ppuscroll: u8,
-    pub ppuaddr: u8,
+    /// Read buffer of PPUDATA. Holds the value fetched by the previous read
     pub ppudata: u8,
     pub oamdma: u8,
+    /// Last value written to or read from any register
+    pub io_latch: u8,
 }
 
 impl Registers {
+    pub fn control_flags(&self) -> ControlFlags {
+        ControlFlags::from(self.ppuctrl)
+    }
+
+    pub fn mask_flags(&self) -> MaskFlags {
+        MaskFlags::from(self.ppumask)
+    }
+
     pub fn status_flags(&self) -> StatusFlags {
         StatusFlags::from(self.ppustatus)
     }
@@ -74,6 +433,194 @@ impl Registers {
     }
 }
 
+/// Registers used internally for VRAM addressing and scrolling
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct InternalRegisters {
+    /// Current VRAM address (15 bits)
+    pub v: u16,
+    /// Temporary VRAM address (15 bits). Holds the address of the top left onscreen tile
+    pub t: u16,
+    /// Fine X scroll (3 bits)
+    pub x: u8,
+    /// First or second write toggle shared by PPUSCROLL and PPUADDR
+    pub w: bool,
+}
+
+impl InternalRegisters {
+    const COARSE_X: u16 = 0x001F;
+    const COARSE_Y: u16 = 0x03E0;
+    const FINE_Y: u16 = 0x7000;
+    const HORIZONTAL_NAME_TABLE: u16 = 0x0400;
+    const VERTICAL_NAME_TABLE: u16 = 0x0800;
+
+    /// Moves `v` to the next tile horizontally, wrapping into the next name table
+    pub fn increment_coarse_x(&mut self) {
+        match self.v & Self::COARSE_X {
+            31 => self.v = (self.v & !Self::COARSE_X) ^ Self::HORIZONTAL_NAME_TABLE,
+            _ => self.v += 1,
+        }
+    }
+
+    /// Moves `v` to the next pixel row, wrapping into the next name table after the 30th tile row
+    pub fn increment_y(&mut self) {
+        if (self.v & Self::FINE_Y) != Self::FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !Self::FINE_Y;
+        let coarse_y = match (self.v & Self::COARSE_Y) >> 5 {
+            29 => {
+                self.v ^= Self::VERTICAL_NAME_TABLE;
+                0
+            }
+            // Coarse Y can be set out of bounds, in which case it wraps without switching name tables
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        self.v = (self.v & !Self::COARSE_Y) | (coarse_y << 5);
+    }
+
+    pub fn copy_horizontal_position(&mut self) {
+        let mask = Self::COARSE_X | Self::HORIZONTAL_NAME_TABLE;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    pub fn copy_vertical_position(&mut self) {
+        let mask = Self::FINE_Y | Self::VERTICAL_NAME_TABLE | Self::COARSE_Y;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+}
+
+/// Latches and shift registers of the background rendering pipeline
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+struct Background {
+    name_table_byte: u8,
+    attribute_bits: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_low_shifter: u16,
+    pattern_high_shifter: u16,
+    attribute_low_shifter: u16,
+    attribute_high_shifter: u16,
+}
+
+impl Background {
+    /// Loads the latched tile into the low 8 bits of the shift registers
+    fn load_shifters(&mut self) {
+        let expand = |bit: u8| match bit {
+            0 => 0x0000,
+            _ => 0x00FF,
+        };
+        self.pattern_low_shifter = (self.pattern_low_shifter & 0xFF00) | self.pattern_low as u16;
+        self.pattern_high_shifter = (self.pattern_high_shifter & 0xFF00) | self.pattern_high as u16;
+        self.attribute_low_shifter = (self.attribute_low_shifter & 0xFF00) | expand(self.attribute_bits & 0x01);
+        self.attribute_high_shifter = (self.attribute_high_shifter & 0xFF00) | expand(self.attribute_bits & 0x02);
+    }
+
+    fn shift(&mut self) {
+        self.pattern_low_shifter <<= 1;
+        self.pattern_high_shifter <<= 1;
+        self.attribute_low_shifter <<= 1;
+        self.attribute_high_shifter <<= 1;
+    }
+
+    /// Returns the 2-bit pixel value and the palette of the current pixel, offset by the fine X scroll
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 0x8000 >> fine_x;
+        let select = |shifter: u16, value: u8| match shifter & bit {
+            0 => 0,
+            _ => value,
+        };
+        (
+            select(self.pattern_low_shifter, 0x01) | select(self.pattern_high_shifter, 0x02),
+            select(self.attribute_low_shifter, 0x01) | select(self.attribute_high_shifter, 0x02),
+        )
+    }
+}
+
+/// A sprite copied from OAM along with its pattern fetched for the scanline
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+struct Sprite {
+    y: u8,
+    tile_index: u8,
+    attributes: u8,
+    x: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    sprite_0: bool,
+}
+
+impl Sprite {
+    const PALETTE_VALUE: u8 = 0b0000_0011;
+    const BEHIND_BACKGROUND_VALUE: u8 = 0b0010_0000;
+    const FLIP_HORIZONTALLY_VALUE: u8 = 0b0100_0000;
+    const FLIP_VERTICALLY_VALUE: u8 = 0b1000_0000;
+
+    fn from_oam_entry(entry: &[u8], sprite_0: bool) -> Self {
+        Self {
+            y: entry[0],
+            tile_index: entry[1],
+            attributes: entry[2],
+            x: entry[3],
+            sprite_0,
+            ..Default::default()
+        }
+    }
+
+    fn behind_background(&self) -> bool {
+        (self.attributes & Self::BEHIND_BACKGROUND_VALUE) != 0
+    }
+
+    fn flip_vertically(&self) -> bool {
+        (self.attributes & Self::FLIP_VERTICALLY_VALUE) != 0
+    }
+
+    /// Horizontal flipping is done once at fetch time by reversing the pattern bits
+    fn flip_pattern(&self, pattern: u8) -> u8 {
+        match (self.attributes & Self::FLIP_HORIZONTALLY_VALUE) != 0 {
+            true => pattern.reverse_bits(),
+            false => pattern,
+        }
+    }
+
+    /// Returns the 2-bit pixel value of the sprite at screen position `x`
+    fn pixel(&self, x: u8) -> u8 {
+        match x.checked_sub(self.x) {
+            Some(offset) if offset < 8 => {
+                let bit = 7 - offset;
+                ((self.pattern_low >> bit) & 0x01) | (((self.pattern_high >> bit) & 0x01) << 1)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Sprites use the upper 4 palettes, starting at $3F10
+    fn palette_address(&self, pixel: u8) -> u16 {
+        0x3F10 | (((self.attributes & Self::PALETTE_VALUE) as u16) << 2) | pixel as u16
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+struct Sprites {
+    /// Sprites found by sprite evaluation for the next scanline
+    secondary_oam: [Sprite; SPRITES_PER_SCANLINE],
+    secondary_oam_count: usize,
+    /// Sprites drawn on the current scanline
+    scanline: [Sprite; SPRITES_PER_SCANLINE],
+    scanline_count: usize,
+}
+
+impl Sprites {
+    /// Returns the first opaque sprite pixel at `x`. Sprites earlier in OAM have priority over later ones
+    fn pixel(&self, x: u8) -> Option<(u8, Sprite)> {
+        self.scanline[..self.scanline_count]
+            .iter()
+            .map(|sprite| (sprite.pixel(x), *sprite))
+            .find(|(pixel, _)| *pixel != 0)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct OAM(pub [u8; OAM_SIZE]);
 
@@ -83,6 +630,80 @@ impl OAM {
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct ControlFlags {
+    pub vram_address_increment: u16,
+    pub sprite_pattern_table_address: u16,
+    pub background_pattern_table_address: u16,
+    pub sprite_height: u32,
+    pub nmi_enabled: bool,
+}
+
+impl ControlFlags {
+    const VRAM_ADDRESS_INCREMENT_VALUE: u8 = 0b0000_0100;
+    const SPRITE_PATTERN_TABLE_VALUE: u8 = 0b0000_1000;
+    const BACKGROUND_PATTERN_TABLE_VALUE: u8 = 0b0001_0000;
+    const SPRITE_SIZE_VALUE: u8 = 0b0010_0000;
+    const NMI_ENABLED_VALUE: u8 = 0b1000_0000;
+}
+
+impl From<u8> for ControlFlags {
+    fn from(value: u8) -> Self {
+        Self {
+            vram_address_increment: match (value & Self::VRAM_ADDRESS_INCREMENT_VALUE) != 0 {
+                false => 1,
+                true => 32,
+            },
+            sprite_pattern_table_address: match (value & Self::SPRITE_PATTERN_TABLE_VALUE) != 0 {
+                false => 0x0000,
+                true => 0x1000,
+            },
+            background_pattern_table_address: match (value & Self::BACKGROUND_PATTERN_TABLE_VALUE) != 0 {
+                false => 0x0000,
+                true => 0x1000,
+            },
+            sprite_height: match (value & Self::SPRITE_SIZE_VALUE) != 0 {
+                false => 8,
+                true => 16,
+            },
+            nmi_enabled: (value & Self::NMI_ENABLED_VALUE) != 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct MaskFlags {
+    pub grayscale: bool,
+    pub show_background_left: bool,
+    pub show_sprites_left: bool,
+    pub show_background: bool,
+    pub show_sprites: bool,
+}
+
+impl MaskFlags {
+    const GRAYSCALE_VALUE: u8 = 0b0000_0001;
+    const SHOW_BACKGROUND_LEFT_VALUE: u8 = 0b0000_0010;
+    const SHOW_SPRITES_LEFT_VALUE: u8 = 0b0000_0100;
+    const SHOW_BACKGROUND_VALUE: u8 = 0b0000_1000;
+    const SHOW_SPRITES_VALUE: u8 = 0b0001_0000;
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.show_background || self.show_sprites
+    }
+}
+
+impl From<u8> for MaskFlags {
+    fn from(value: u8) -> Self {
+        Self {
+            grayscale: (value & Self::GRAYSCALE_VALUE) != 0,
+            show_background_left: (value & Self::SHOW_BACKGROUND_LEFT_VALUE) != 0,
+            show_sprites_left: (value & Self::SHOW_SPRITES_LEFT_VALUE) != 0,
+            show_background: (value & Self::SHOW_BACKGROUND_VALUE) != 0,
+            show_sprites: (value & Self::SHOW_SPRITES_VALUE) != 0,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub struct StatusFlags {
     pub least_significant_bits: u8,
@@ -128,6 +749,7 @@ impl Into<u8> for StatusFlags {
 pub struct Clock {
     pub cycle: u32,
     pub scanline: u32,
+    pub odd_frame: bool,
 }
 
 impl Clock {
@@ -136,8 +758,11 @@ impl Clock {
             result if result <= 340 => result,
             _ => {
                 self.scanline = match self.scanline + 1 {
-                    result if result <= 261 => result,
-                    _ => 0,
+                    result if result <= PRE_RENDER_SCANLINE => result,
+                    _ => {
+                        self.odd_frame = !self.odd_frame;
+                        0
+                    }
                 };
                 0
             }
@@ -145,89 +770,6 @@ impl Clock {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub struct PatternTables<'a> {
-    pub left: &'a [u8],
-    pub right: &'a [u8],
-}
-
-impl<'a> PatternTables<'a> {
-    pub fn new(left: &'a [u8], right: &'a [u8]) -> Result<Self, String> {
-        match (left.len(), right.len()) {
-            (PATTERN_TABLE_SECTION_SIZE, PATTERN_TABLE_SECTION_SIZE) => Ok(Self { left, right }),
-            (left_len, right_len) => Err(format!(
-                "Invalid pattern table slice size. Meeds to be {} bytes, got: (left: {}, right: {})",
-                PATTERN_TABLE_SECTION_SIZE, left_len, right_len,
-            )),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub struct NameTable<'a>(pub &'a [u8]);
-
-impl<'a> NameTable<'a> {
-    pub fn with_slice(slice: &'a [u8]) -> Result<Self, String> {
-        match slice.len() {
-            NAME_TABLE_SIZE => Ok(Self(slice)),
-            len => Err(format!("Invalid name table size. Needs to be {} bytes, got: {}", NAME_TABLE_SIZE, len)),
-        }
-    }
-
-    pub fn attribute_table(&self) -> &[u8] {
-        &self.0[self.0.len() - 64..]
-    }
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub struct NameTables<'a> {
-    top_left: NameTable<'a>,
-    top_right: NameTable<'a>,
-    bottom_left: NameTable<'a>,
-    bottom_right: NameTable<'a>,
-}
-
-impl<'a> NameTables<'a> {
-    pub fn new(
-        top_left: NameTable<'a>,
-        top_right: NameTable<'a>,
-        bottom_left: NameTable<'a>,
-        bottom_right: NameTable<'a>,
-    ) -> Self {
-        Self{ top_left, top_right, bottom_left, bottom_right }
-    }
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
-pub struct Tile(pub [[u8; 8]; 8]);
-
-impl Tile {
-    pub fn from_pattern_table_slice(slice: &[u8]) -> Result<Self, String> {
-        match slice.len() {
-            PATTERN_TILE_SIZE => {
-                let mut tile = [[0u8; 8]; 8];
-
-                let (first_planes, second_planes) = slice.split_at(8);
-                let zipped_planes = first_planes.iter().zip(second_planes);
-
-                for (row, planes) in &mut tile.iter_mut().zip(zipped_planes) {
-                    for (bit_index, color) in &mut row.iter_mut().rev().enumerate() {
-                        let color_0 = (planes.0 >> bit_index) & 0x01;
-                        let color_1 = ((planes.1 >> bit_index) & 0x01) << 1;
-                        *color = color_0 | color_1;
-                    }
-                }
-
-                Ok(Self(tile))
-            }
-            n => Err(format!(
-                "Invalid tile slice size. A tile needs to be {} bytes but got {}",
-                PATTERN_TILE_SIZE, n
-            )),
-        }
-    }
-}
-
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub struct Color {
     pub r: u8,
@@ -251,120 +793,580 @@ pub struct Palette;
 impl Palette {
     const COLORS: [Color; 0x40] = [
         Color{ r: 84, g: 84, b: 84 },
-        Color{ r: 0, g: 30, b: 3 },
-        Color{ r: 8, g: 16, b: 3 },
-        Color{ r: 48, g: 0, b: 3 },
-        Color{ r: 68, g: 0, b: 3 },
-        Color{ r: 92, g: 0, b: 3 },
-        Color{ r: 84, g: 4, b: 3 },
-        Color{ r: 60, g: 24, b: 3 },
-        Color{ r: 32, g: 42, b: 3 },
-        Color{ r: 8, g: 58, b: 3 },
-        Color{ r: 0, g: 64, b: 3 },
-        Color{ r: 0, g: 60, b: 3 },
-        Color{ r: 0, g: 50, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 152, g: 150, b: 3 },
-        Color{ r: 8, g: 76, b: 3 },
-        Color{ r: 48, g: 50, b: 3 },
-        Color{ r: 92, g: 30, b: 3 },
-        Color{ r: 136, g: 20, b: 3 },
-        Color{ r: 160, g: 20, b: 3 },
-        Color{ r: 152, g: 34, b: 3 },
-        Color{ r: 120, g: 60, b: 3 },
-        Color{ r: 84, g: 90, b: 3 },
-        Color{ r: 40, g: 114, b: 3 },
-        Color{ r: 8, g: 124, b: 3 },
-        Color{ r: 0, g: 118, b: 3 },
-        Color{ r: 0, g: 102, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 236, g: 238, b: 3 },
-        Color{ r: 76, g: 154, b: 3 },
-        Color{ r: 120, g: 124, b: 3 },
-        Color{ r: 176, g: 98, b: 3 },
-        Color{ r: 228, g: 84, b: 3 },
-        Color{ r: 236, g: 88, b: 3 },
-        Color{ r: 236, g: 106, b: 3 },
-        Color{ r: 212, g: 136, b: 3 },
-        Color{ r: 160, g: 170, b: 3 },
-        Color{ r: 116, g: 196, b: 3 },
-        Color{ r: 76, g: 208, b: 3 },
-        Color{ r: 56, g: 204, b: 3 },
-        Color{ r: 56, g: 180, b: 3 },
-        Color{ r: 60, g: 60, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 236, g: 238, b: 3 },
-        Color{ r: 168, g: 204, b: 3 },
-        Color{ r: 188, g: 188, b: 3 },
-        Color{ r: 212, g: 178, b: 3 },
-        Color{ r: 236, g: 174, b: 3 },
-        Color{ r: 236, g: 174, b: 3 },
-        Color{ r: 236, g: 180, b: 3 },
-        Color{ r: 228, g: 196, b: 3 },
-        Color{ r: 204, g: 210, b: 3 },
-        Color{ r: 180, g: 222, b: 3 },
-        Color{ r: 168, g: 226, b: 3 },
-        Color{ r: 152, g: 226, b: 3 },
-        Color{ r: 160, g: 214, b: 3 },
-        Color{ r: 160, g: 162, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
-        Color{ r: 0, g: 0, b: 3 },
+        Color{ r: 0, g: 30, b: 116 },
+        Color{ r: 8, g: 16, b: 144 },
+        Color{ r: 48, g: 0, b: 136 },
+        Color{ r: 68, g: 0, b: 100 },
+        Color{ r: 92, g: 0, b: 48 },
+        Color{ r: 84, g: 4, b: 0 },
+        Color{ r: 60, g: 24, b: 0 },
+        Color{ r: 32, g: 42, b: 0 },
+        Color{ r: 8, g: 58, b: 0 },
+        Color{ r: 0, g: 64, b: 0 },
+        Color{ r: 0, g: 60, b: 0 },
+        Color{ r: 0, g: 50, b: 60 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 152, g: 150, b: 152 },
+        Color{ r: 8, g: 76, b: 196 },
+        Color{ r: 48, g: 50, b: 236 },
+        Color{ r: 92, g: 30, b: 228 },
+        Color{ r: 136, g: 20, b: 176 },
+        Color{ r: 160, g: 20, b: 100 },
+        Color{ r: 152, g: 34, b: 32 },
+        Color{ r: 120, g: 60, b: 0 },
+        Color{ r: 84, g: 90, b: 0 },
+        Color{ r: 40, g: 114, b: 0 },
+        Color{ r: 8, g: 124, b: 0 },
+        Color{ r: 0, g: 118, b: 40 },
+        Color{ r: 0, g: 102, b: 120 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 236, g: 238, b: 236 },
+        Color{ r: 76, g: 154, b: 236 },
+        Color{ r: 120, g: 124, b: 236 },
+        Color{ r: 176, g: 98, b: 236 },
+        Color{ r: 228, g: 84, b: 236 },
+        Color{ r: 236, g: 88, b: 180 },
+        Color{ r: 236, g: 106, b: 100 },
+        Color{ r: 212, g: 136, b: 32 },
+        Color{ r: 160, g: 170, b: 0 },
+        Color{ r: 116, g: 196, b: 0 },
+        Color{ r: 76, g: 208, b: 32 },
+        Color{ r: 56, g: 204, b: 108 },
+        Color{ r: 56, g: 180, b: 204 },
+        Color{ r: 60, g: 60, b: 60 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 236, g: 238, b: 236 },
+        Color{ r: 168, g: 204, b: 236 },
+        Color{ r: 188, g: 188, b: 236 },
+        Color{ r: 212, g: 178, b: 236 },
+        Color{ r: 236, g: 174, b: 236 },
+        Color{ r: 236, g: 174, b: 212 },
+        Color{ r: 236, g: 180, b: 176 },
+        Color{ r: 228, g: 196, b: 144 },
+        Color{ r: 204, g: 210, b: 120 },
+        Color{ r: 180, g: 222, b: 120 },
+        Color{ r: 168, g: 226, b: 144 },
+        Color{ r: 152, g: 226, b: 180 },
+        Color{ r: 160, g: 214, b: 228 },
+        Color{ r: 160, g: 162, b: 160 },
+        Color{ r: 0, g: 0, b: 0 },
+        Color{ r: 0, g: 0, b: 0 },
     ];
+
+    pub fn color(index: u8) -> Color {
+        Self::COLORS[(index & 0x3F) as usize]
+    }
 }
 
-pub struct MMU<'a, 'b> {
-    pub pattern_tables: PatternTables<'a>,
-    pub name_tables: NameTables<'b>,
+pub struct MMU<'a> {
+    ppu: &'a mut PPU,
+    mapper: Option<&'a mut dyn Mapper>,
 }
 
-impl<'a, 'b> Memory for MMU<'a, 'b> {
-    fn read(&self, address: u16) -> Option<u8> {
+impl<'a> MMU<'a> {
+    pub fn new(ppu: &'a mut PPU, mapper: Option<&'a mut dyn Mapper>) -> Self {
+        Self { ppu, mapper }
+    }
+
+    fn name_table_memory(&mut self, address: u16) -> &mut u8 {
+        let address = (address - 0x2000) as usize % (NAME_TABLE_SIZE * 4);
+        let mirroring = match &self.mapper {
+            Some(mapper) => mapper.mirroring(),
+            None => Mirroring::default(),
+        };
+        let name_table = mirroring.name_table_index(address / NAME_TABLE_SIZE);
+        match name_table * NAME_TABLE_SIZE + address % NAME_TABLE_SIZE {
+            index if index < INTERNAL_MEMORY_SIZE => &mut self.ppu.internal_memory[index],
+            index => &mut self.ppu.four_screen_memory[index - INTERNAL_MEMORY_SIZE],
+        }
+    }
+
+    fn palette_ram_index(address: u16) -> usize {
+        match address as usize % PALETTE_RAM_SIZE {
+            // The backdrop colors of the sprite palettes mirror the ones of the background palettes
+            index @ (0x10 | 0x14 | 0x18 | 0x1C) => index - 0x10,
+            index => index,
+        }
+    }
+}
+
+impl<'a> Memory for MMU<'a> {
+    fn read(&mut self, address: u16) -> Option<u8> {
         match address {
-            0x0000..=0x0FFF => Some(self.pattern_tables.left[address as usize]),
-            start @ 0x1000..=0x1FFF => Some(self.pattern_tables.right[(address - start) as usize]),
-            start @ 0x2000..=0x23FF => Some(self.name_tables.top_left.0[(address - start) as usize]),
-            start @ 0x2400..=0x27FF => Some(self.name_tables.top_right.0[(address - start) as usize]),
-            start @ 0x2800..=0x2BFF => Some(self.name_tables.bottom_left.0[(address - start) as usize]),
-            start @ 0x2C00..=0x2FFF => Some(self.name_tables.bottom_right.0[(address - start) as usize]),
-            0x3000..=0x3EFF => self.read(address - 0x1000), // mirrors 0x2000..=0x2EFF
-            0x3F00..=0x3FFF => unimplemented!(),            // palette ram indexes
+            0x0000..=0x1FFF => self.mapper.as_deref_mut().map(|mapper| mapper.ppu_read(address)),
+            0x2000..=0x3EFF => Some(*self.name_table_memory(address)),
+            0x3F00..=0x3FFF => Some(self.ppu.palette_ram[Self::palette_ram_index(address)]),
             _ => None,
         }
     }
 
     fn write(&mut self, address: u16, value: u8) {
-        unimplemented!()
+        match address {
+            0x0000..=0x1FFF => {
+                if let Some(mapper) = self.mapper.as_deref_mut() {
+                    mapper.ppu_write(address, value);
+                }
+            }
+            0x2000..=0x3EFF => *self.name_table_memory(address) = value,
+            0x3F00..=0x3FFF => self.ppu.palette_ram[Self::palette_ram_index(address)] = value & 0b0011_1111,
+            _ => panic!("Access violation. Trying to write to PPU address {:#X}", address),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Tile;
+    use super::{InternalRegisters, State, StatusFlags, MMU, PPU, PRE_RENDER_SCANLINE};
+    use crate::{
+        hardware::{
+            mapper::{Mapper, NROM},
+            memory::Memory,
+        },
+        rom::Mirroring,
+    };
+
+    fn render_frame(ppu: &mut PPU, mapper: &mut dyn Mapper) {
+        while ppu.step(Some(&mut *mapper)) != Some(State::VBlankToggle(true)) {}
+    }
+
+    fn background_test_setup(ppu: &mut PPU) -> [u8; 0x2000] {
+        // Tile 1 is filled with color 1, tile 2 with color 2
+        let mut chr_rom = [0u8; 0x2000];
+        chr_rom[0x0010..0x0018].copy_from_slice(&[0xFF; 8]);
+        chr_rom[0x0028..0x0030].copy_from_slice(&[0xFF; 8]);
+
+        let mut mmu = MMU::new(ppu, None);
+        mmu.write(0x2000, 0x01);
+        mmu.write(0x2001, 0x02);
+        mmu.write(0x23C0, 0b0000_0001);
+        mmu.write(0x3F00, 0x0F);
+        mmu.write(0x3F05, 0x16);
+        mmu.write(0x3F06, 0x27);
+        ppu.write_register(0x2001, 0b0000_1010, None);
+        chr_rom
+    }
+
+    #[test]
+    fn test_background_rendering() {
+        let mut ppu = PPU::new();
+        let chr_rom = background_test_setup(&mut ppu);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        // The first frame starts without the tiles prefetched on the pre-render scanline
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[0][0..8], [0x16; 8]);
+        assert_eq!(ppu.framebuffer[7][8..16], [0x27; 8]);
+        assert_eq!(ppu.framebuffer[0][16..24], [0x0F; 8]);
+        assert_eq!(ppu.framebuffer[8][0..8], [0x0F; 8]);
+    }
+
+    #[test]
+    fn test_background_fine_x_scroll() {
+        let mut ppu = PPU::new();
+        let chr_rom = background_test_setup(&mut ppu);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2005, 0x04, None);
+        ppu.write_register(0x2005, 0x00, None);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[0][0..4], [0x16; 4]);
+        assert_eq!(ppu.framebuffer[0][4..12], [0x27; 8]);
+        assert_eq!(ppu.framebuffer[0][12], 0x0F);
+    }
+
+    #[test]
+    fn test_background_left_clipping() {
+        let mut ppu = PPU::new();
+        let chr_rom = background_test_setup(&mut ppu);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2001, 0b0000_1000, None);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[0][0..8], [0x0F; 8]);
+        assert_eq!(ppu.framebuffer[0][8..16], [0x27; 8]);
+    }
+
+    fn sprite_test_setup(ppu: &mut PPU, sprites: &[[u8; 4]]) -> [u8; 0x2000] {
+        let mut chr_rom = background_test_setup(ppu);
+        // Tile 3 has its first row half filled with color 1, in both pattern tables
+        chr_rom[0x0030] = 0xF0;
+        chr_rom[0x1030] = 0xF0;
+
+        let mut mmu = MMU::new(ppu, None);
+        mmu.write(0x3F11, 0x05);
+        mmu.write(0x3F15, 0x2A);
+        for (index, sprite) in sprites.iter().enumerate() {
+            ppu.oam.0[index * 4..index * 4 + 4].copy_from_slice(sprite);
+        }
+        // Hide the remaining sprites below the screen
+        for byte in ppu.oam.0[sprites.len() * 4..].iter_mut().step_by(4) {
+            *byte = 0xFF;
+        }
+        ppu.write_register(0x2001, 0b0001_1110, None);
+        chr_rom
+    }
+
+    #[test]
+    fn test_sprite_rendering() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x09, 0x03, 0x00, 0x14]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        // Sprites are drawn one scanline below their OAM Y position
+        assert_eq!(ppu.framebuffer[9][20..24], [0x0F; 4]);
+        assert_eq!(ppu.framebuffer[10][20..24], [0x05; 4]);
+        assert_eq!(ppu.framebuffer[10][24..28], [0x0F; 4]);
+        assert_eq!(ppu.framebuffer[11][20..24], [0x0F; 4]);
+    }
+
+    #[test]
+    fn test_sprite_flipping() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x09, 0x03, 0x40, 0x14], [0x09, 0x03, 0x80, 0x3C]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[10][20..24], [0x0F; 4]);
+        assert_eq!(ppu.framebuffer[10][24..28], [0x05; 4]);
+        assert_eq!(ppu.framebuffer[10][60..64], [0x0F; 4]);
+        assert_eq!(ppu.framebuffer[17][60..64], [0x05; 4]);
+    }
 
     #[test]
-    fn test_tile() {
-        let tile_data: [u8; 16] = [
-            0x41, 0xC2, 0x44, 0x48, 0x10, 0x20, 0x40, 0x80, 0x01, 0x02, 0x04, 0x08, 0x16, 0x21, 0x42, 0x87,
-        ];
-        let tile = Tile::from_pattern_table_slice(&tile_data).unwrap();
-        let Tile(tile_matrix) = tile;
-        assert_eq!(
-            tile_matrix,
-            [
-                [0, 1, 0, 0, 0, 0, 0, 3],
-                [1, 1, 0, 0, 0, 0, 3, 0],
-                [0, 1, 0, 0, 0, 3, 0, 0],
-                [0, 1, 0, 0, 3, 0, 0, 0],
-                [0, 0, 0, 3, 0, 2, 2, 0],
-                [0, 0, 3, 0, 0, 0, 0, 2],
-                [0, 3, 0, 0, 0, 0, 2, 0],
-                [3, 0, 0, 0, 0, 2, 2, 2],
-            ]
+    fn test_sprite_priority() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(
+            &mut ppu,
+            &[
+                [0x00, 0x03, 0x00, 0x00],
+                [0x00, 0x03, 0x21, 0x08],
+                [0x00, 0x03, 0x01, 0x02],
+            ],
         );
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        // Sprite 0 is in front of the background and of sprite 2
+        assert_eq!(ppu.framebuffer[1][0..4], [0x05; 4]);
+        assert_eq!(ppu.framebuffer[1][4..6], [0x2A; 2]);
+        // Sprite 1 is behind the opaque background
+        assert_eq!(ppu.framebuffer[1][8..12], [0x27; 4]);
+    }
+
+    #[test]
+    fn test_sprites_per_scanline_limit() {
+        let mut ppu = PPU::new();
+        let sprites: Vec<[u8; 4]> = (0..9).map(|index| [0x09, 0x03, 0x00, index * 0x10]).collect();
+        let chr_rom = sprite_test_setup(&mut ppu, &sprites);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[10][0x70..0x74], [0x05; 4]);
+        assert_eq!(ppu.framebuffer[10][0x80..0x84], [0x0F; 4]);
+    }
+
+    #[test]
+    fn test_8x16_sprites() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x09, 0x03, 0x00, 0x14]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2000, 0b0010_0000, None);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        // Tile $03 selects tiles $02 and $03 of the pattern table at $1000
+        assert_eq!(ppu.framebuffer[10][20..24], [0x0F; 4]);
+        assert_eq!(ppu.framebuffer[18][20..24], [0x05; 4]);
+    }
+
+    #[test]
+    fn test_sprite_left_clipping() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x09, 0x03, 0x00, 0x06]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2001, 0b0001_1010, None);
+
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.framebuffer[10][6..8], [0x0F; 2]);
+        assert_eq!(ppu.framebuffer[10][8..10], [0x05; 2]);
+    }
+
+    /// Steps the PPU up to the given dot of the current frame
+    fn step_to(ppu: &mut PPU, mapper: &mut dyn Mapper, scanline: u32, cycle: u32) {
+        while ppu.clock.scanline != scanline || ppu.clock.cycle != cycle {
+            ppu.step(Some(&mut *mapper));
+        }
+    }
+
+    #[test]
+    fn test_sprite_0_hit() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x00, 0x03, 0x20, 0x02]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        // A sprite behind the background still triggers the hit
+        render_frame(&mut ppu, &mut mapper);
+        assert!(ppu.registers.status_flags().sprite_0_hit);
+        assert_eq!(ppu.framebuffer[1][2..6], [0x16; 4]);
+
+        step_to(&mut ppu, &mut mapper, PRE_RENDER_SCANLINE, 1);
+        assert!(!ppu.registers.status_flags().sprite_0_hit);
+    }
+
+    #[test]
+    fn test_sprite_0_hit_timing() {
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x00, 0x03, 0x20, 0x02]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        // The first opaque overlapping pixel is at x = 2 on line 1, which is drawn at dot 3
+        step_to(&mut ppu, &mut mapper, 1, 2);
+        assert!(!ppu.registers.status_flags().sprite_0_hit);
+        ppu.step(Some(&mut mapper));
+        assert!(ppu.registers.status_flags().sprite_0_hit);
+    }
+
+    #[test]
+    fn test_sprite_0_hit_clipping() {
+        // Left clipped pixels and the last column never trigger a hit
+        let mut ppu = PPU::new();
+        let chr_rom = sprite_test_setup(&mut ppu, &[[0x00, 0x03, 0x00, 0x04]]);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2001, 0b0001_1010, None);
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+        assert!(!ppu.registers.status_flags().sprite_0_hit);
+
+        let mut ppu = PPU::new();
+        let mut chr_rom = sprite_test_setup(&mut ppu, &[[0x00, 0x03, 0x00, 0xFF]]);
+        MMU::new(&mut ppu, None).write(0x201F, 0x01);
+        chr_rom[0x0030] = 0xFF;
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+        render_frame(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+        assert_eq!(ppu.framebuffer[1][255], 0x05);
+        assert!(!ppu.registers.status_flags().sprite_0_hit);
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let mut ppu = PPU::new();
+        let sprites: Vec<[u8; 4]> = (0..9).map(|index| [0x09, 0x03, 0x00, index * 0x10]).collect();
+        let chr_rom = sprite_test_setup(&mut ppu, &sprites);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        step_to(&mut ppu, &mut mapper, 9, 256);
+        assert!(!ppu.registers.status_flags().sprite_overflow);
+        step_to(&mut ppu, &mut mapper, 9, 258);
+        assert!(ppu.registers.status_flags().sprite_overflow);
+
+        step_to(&mut ppu, &mut mapper, PRE_RENDER_SCANLINE, 2);
+        assert!(!ppu.registers.status_flags().sprite_overflow);
+    }
+
+    #[test]
+    fn test_sprite_overflow_hardware_bug() {
+        // The 9th sprite is on the scanline, but its X position is checked instead of its Y coordinate
+        let mut ppu = PPU::new();
+        let mut sprites: Vec<[u8; 4]> = (0..8).map(|index| [0x09, 0x03, 0x00, index * 0x10]).collect();
+        sprites.push([0xFF, 0x03, 0x00, 0x00]);
+        sprites.push([0x09, 0x20, 0x00, 0x00]);
+        let chr_rom = sprite_test_setup(&mut ppu, &sprites);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        step_to(&mut ppu, &mut mapper, 9, 258);
+        assert!(!ppu.registers.status_flags().sprite_overflow);
+
+        // A sprite off the scanline whose tile index looks like an in range Y coordinate sets the flag
+        let mut ppu = PPU::new();
+        let mut sprites: Vec<[u8; 4]> = (0..8).map(|index| [0x09, 0x03, 0x00, index * 0x10]).collect();
+        sprites.push([0xFF, 0x03, 0x00, 0x00]);
+        sprites.push([0xFF, 0x05, 0x00, 0x00]);
+        let chr_rom = sprite_test_setup(&mut ppu, &sprites);
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        step_to(&mut ppu, &mut mapper, 9, 258);
+        assert!(ppu.registers.status_flags().sprite_overflow);
+    }
+
+    #[test]
+    fn test_scroll_increments() {
+        let mut registers = InternalRegisters {
+            v: 0x001F,
+            ..Default::default()
+        };
+        registers.increment_coarse_x();
+        assert_eq!(registers.v, 0x0400);
+
+        registers.v = 0x73A0;
+        registers.increment_y();
+        assert_eq!(registers.v, 0x0800);
+
+        registers.v = 0x73E0;
+        registers.increment_y();
+        assert_eq!(registers.v, 0x0000);
+
+        registers.v = 0x3020;
+        registers.increment_y();
+        assert_eq!(registers.v, 0x4020);
+    }
+
+    #[test]
+    fn test_name_table_mirroring() {
+        let mut ppu = PPU::new();
+        let mut mapper = NROM::new(vec![], vec![], 0, 0, Mirroring::Horizontal);
+        let mut mmu = MMU::new(&mut ppu, Some(&mut mapper));
+        mmu.write(0x2001, 0x01);
+        mmu.write(0x2C02, 0x02);
+        assert_eq!(mmu.read(0x2401), Some(0x01));
+        assert_eq!(mmu.read(0x2802), Some(0x02));
+        assert_eq!(mmu.read(0x3401), Some(0x01));
+
+        let mut ppu = PPU::new();
+        let mut mapper = NROM::new(vec![], vec![], 0, 0, Mirroring::Vertical);
+        let mut mmu = MMU::new(&mut ppu, Some(&mut mapper));
+        mmu.write(0x2001, 0x01);
+        mmu.write(0x2C02, 0x02);
+        assert_eq!(mmu.read(0x2801), Some(0x01));
+        assert_eq!(mmu.read(0x2402), Some(0x02));
+
+        let mut ppu = PPU::new();
+        let mut mapper = NROM::new(vec![], vec![], 0, 0, Mirroring::SingleScreenUpper);
+        let mut mmu = MMU::new(&mut ppu, Some(&mut mapper));
+        mmu.write(0x2001, 0x01);
+        assert_eq!(mmu.read(0x2C01), Some(0x01));
+        assert_eq!(ppu.internal_memory[0x0401], 0x01);
+
+        let mut ppu = PPU::new();
+        let mut mapper = NROM::new(vec![], vec![], 0, 0, Mirroring::FourScreen);
+        let mut mmu = MMU::new(&mut ppu, Some(&mut mapper));
+        mmu.write(0x2C01, 0x01);
+        assert_eq!(mmu.read(0x2001), Some(0x00));
+        assert_eq!(ppu.four_screen_memory[0x0401], 0x01);
+    }
+
+    #[test]
+    fn test_palette_ram() {
+        let mut ppu = PPU::new();
+        MMU::new(&mut ppu, None).write(0x3F10, 0x01);
+        MMU::new(&mut ppu, None).write(0x3F05, 0xFF);
+        assert_eq!(MMU::new(&mut ppu, None).read(0x3F00), Some(0x01));
+        assert_eq!(MMU::new(&mut ppu, None).read(0x3F25), Some(0x3F));
+        assert_eq!(MMU::new(&mut ppu, None).read(0x3F15), Some(0x00));
+    }
+
+    #[test]
+    fn test_ppuaddr_and_ppudata() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2006, 0x21, None);
+        ppu.write_register(0x2006, 0x08, None);
+        assert_eq!(ppu.internal_registers.v, 0x2108);
+
+        ppu.write_register(0x2007, 0x01, None);
+        ppu.write_register(0x2007, 0x02, None);
+        assert_eq!(ppu.internal_registers.v, 0x210A);
+
+        ppu.write_register(0x2000, 0b0000_0100, None);
+        ppu.write_register(0x2006, 0x21, None);
+        ppu.write_register(0x2006, 0x08, None);
+        ppu.write_register(0x2007, 0x03, None);
+        assert_eq!(ppu.internal_registers.v, 0x2128);
+        assert_eq!(ppu.internal_memory[0x0108..0x010A], [0x03, 0x02]);
+    }
+
+    #[test]
+    fn test_ppudata_read_buffer() {
+        let mut chr_rom = [0u8; 0x2000];
+        chr_rom[0x0010] = 0xAA;
+        chr_rom[0x0011] = 0xBB;
+        let mut mapper = NROM::new(vec![], chr_rom.to_vec(), 0, 0, Mirroring::Horizontal);
+
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2006, 0x00, None);
+        ppu.write_register(0x2006, 0x10, None);
+        ppu.read_register(0x2007, Some(&mut mapper));
+        assert_eq!(ppu.read_register(0x2007, Some(&mut mapper)), 0xAA);
+        assert_eq!(ppu.read_register(0x2007, Some(&mut mapper)), 0xBB);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = NROM::new(vec![], vec![], 0, 0, Mirroring::Horizontal);
+
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2006, 0x10, None);
+        ppu.write_register(0x2006, 0x20, None);
+        ppu.write_register(0x2007, 0xAA, Some(&mut mapper));
+        ppu.write_register(0x2007, 0xBB, Some(&mut mapper));
+        assert_eq!(mapper.ppu_read(0x1020), 0xAA);
+        assert_eq!(mapper.ppu_read(0x1021), 0xBB);
+
+        let mut mapper = NROM::new(vec![], vec![0x01; 0x2000], 0, 0, Mirroring::Horizontal);
+        ppu.write_register(0x2007, 0xCC, Some(&mut mapper));
+        assert_eq!(mapper.ppu_read(0x1022), 0x01);
+    }
+
+    #[test]
+    fn test_ppuscroll() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2000, 0b0000_0011, None);
+        ppu.write_register(0x2005, 0b0111_1101, None);
+        ppu.write_register(0x2005, 0b0101_1110, None);
+
+        let internal_registers = ppu.internal_registers;
+        assert_eq!(internal_registers.t, 0b110_1101_0110_1111);
+        assert_eq!(internal_registers.x, 0b101);
+        assert!(!internal_registers.w);
+    }
+
+    #[test]
+    fn test_ppustatus_read() {
+        let mut ppu = PPU::new();
+        ppu.registers.set_status_flags(StatusFlags {
+            vblank: true,
+            ..Default::default()
+        });
+        ppu.write_register(0x2006, 0x21, None);
+
+        assert_eq!(ppu.read_register(0x2002, None), 0b1000_0001);
+        assert!(!ppu.registers.status_flags().vblank);
+        assert!(!ppu.internal_registers.w);
+    }
+
+    #[test]
+    fn test_oamdata() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0x2003, 0x10, None);
+        ppu.write_register(0x2004, 0x01, None);
+        ppu.write_register(0x2004, 0x02, None);
+        assert_eq!(ppu.oam.0[0x10..0x12], [0x01, 0x02]);
+        assert_eq!(ppu.registers.oamaddr, 0x12);
+
+        ppu.write_register(0x2003, 0x11, None);
+        assert_eq!(ppu.read_register(0x2004, None), 0x02);
+        assert_eq!(ppu.registers.oamaddr, 0x11);
     }
 }
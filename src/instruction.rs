@@ -1,8 +1,8 @@
 use crate::{
     error::InvalidOpCode,
     hardware::{
-        cpu::{AddressingMode, Flags, Sign, MMU},
-        memory::Stack,
+        cpu::{crosses_page, AddressingMode, Flags, Interrupt, Sign, INTERRUPT_CYCLES, MMU, UNUSED_FLAG_VALUE},
+        memory::{Memory, Stack},
     },
 };
 use log::debug;
@@ -71,10 +71,29 @@ pub enum InstructionType {
 impl InstructionType {
     pub fn increments_pc(&self) -> bool {
         match self {
-            InstructionType::JMP | InstructionType::JSR | InstructionType::RTS | InstructionType::RTI => false,
+            InstructionType::JMP
+            | InstructionType::JSR
+            | InstructionType::RTS
+            | InstructionType::RTI
+            | InstructionType::BRK => false,
             _ => true,
         }
     }
+
+    pub fn adds_cycle_on_page_cross(&self) -> bool {
+        matches!(
+            self,
+            InstructionType::ADC
+                | InstructionType::SBC
+                | InstructionType::LDA
+                | InstructionType::LDX
+                | InstructionType::LDY
+                | InstructionType::AND
+                | InstructionType::ORA
+                | InstructionType::EOR
+                | InstructionType::CMP
+        )
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -464,6 +483,51 @@ impl Instruction {
             addressing_mode,
         }
     }
+
+    /// Base cycle count of the instruction, without page crossing and branch penalties
+    pub fn cycles(&self) -> u32 {
+        match self.instruction_type {
+            InstructionType::BRK => 7,
+            InstructionType::JSR | InstructionType::RTS | InstructionType::RTI => 6,
+            InstructionType::PHA | InstructionType::PHP => 3,
+            InstructionType::PLA | InstructionType::PLP => 4,
+            InstructionType::JMP => match self.addressing_mode {
+                AddressingMode::Indirect(_) => 5,
+                _ => 3,
+            },
+            InstructionType::ASL
+            | InstructionType::LSR
+            | InstructionType::ROL
+            | InstructionType::ROR
+            | InstructionType::INC
+            | InstructionType::DEC => match self.addressing_mode {
+                AddressingMode::Accumulator => 2,
+                AddressingMode::ZeroPage(_) => 5,
+                AddressingMode::ZeroPageX(_) | AddressingMode::Absolute(_) => 6,
+                _ => 7,
+            },
+            InstructionType::STA | InstructionType::STX | InstructionType::STY => match self.addressing_mode {
+                AddressingMode::ZeroPage(_) => 3,
+                AddressingMode::ZeroPageX(_) | AddressingMode::ZeroPageY(_) | AddressingMode::Absolute(_) => 4,
+                AddressingMode::AbsoluteX(_) | AddressingMode::AbsoluteY(_) => 5,
+                _ => 6,
+            },
+            _ => match self.addressing_mode {
+                AddressingMode::Implied
+                | AddressingMode::Accumulator
+                | AddressingMode::Immediate(_)
+                | AddressingMode::Relative(_) => 2,
+                AddressingMode::ZeroPage(_) => 3,
+                AddressingMode::ZeroPageX(_)
+                | AddressingMode::ZeroPageY(_)
+                | AddressingMode::Absolute(_)
+                | AddressingMode::AbsoluteX(_)
+                | AddressingMode::AbsoluteY(_) => 4,
+                AddressingMode::IndirectIndexed(_) | AddressingMode::Indirect(_) => 5,
+                AddressingMode::IndexedIndirect(_) => 6,
+            },
+        }
+    }
 }
 
 impl Display for Instruction {
@@ -476,17 +540,24 @@ impl Display for Instruction {
     }
 }
 
-pub struct InstructionExecutor<'a, 'mmu, 'mapped> {
-    mmu: &'a mut MMU<'mmu, 'mapped>,
+pub struct InstructionExecutor<'a, 'mmu> {
+    mmu: &'a mut MMU<'mmu>,
 }
 
-impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
-    pub fn new(mmu: &'a mut MMU<'mmu, 'mapped>) -> Self {
+impl<'a, 'mmu> InstructionExecutor<'a, 'mmu> {
+    pub fn new(mmu: &'a mut MMU<'mmu>) -> Self {
         Self { mmu }
     }
 
-    pub fn execute(&mut self, instruction: Instruction) {
+    pub fn execute(&mut self, instruction: Instruction) -> u32 {
         //debug!("Executing {}", instruction);
+        let mut extra_cycles = match instruction.instruction_type.adds_cycle_on_page_cross()
+            && self.mmu.page_crossed_by_mode(instruction.addressing_mode)
+        {
+            true => 1,
+            false => 0,
+        };
+
         match instruction.instruction_type {
             InstructionType::ADC => {
                 let value = self.read_8_bit_value(instruction);
@@ -527,8 +598,9 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
             InstructionType::STX => self.write_8_bit_value(instruction, self.mmu.cpu().registers.x),
             InstructionType::STY => self.write_8_bit_value(instruction, self.mmu.cpu().registers.y),
             InstructionType::INC => {
-                let value = self.read_8_bit_value(instruction).wrapping_add(1);
-                self.write_8_bit_value(instruction, value);
+                let old_value = self.read_8_bit_value(instruction);
+                let value = old_value.wrapping_add(1);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_zero_and_negative_flags(value);
             }
             InstructionType::INX => {
@@ -542,8 +614,9 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 self.update_zero_and_negative_flags(value);
             }
             InstructionType::DEC => {
-                let value = self.read_8_bit_value(instruction).wrapping_sub(1);
-                self.write_8_bit_value(instruction, value);
+                let old_value = self.read_8_bit_value(instruction);
+                let value = old_value.wrapping_sub(1);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_zero_and_negative_flags(value);
             }
             InstructionType::DEX => {
@@ -559,13 +632,13 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
             InstructionType::ASL => {
                 let old_value = self.read_8_bit_value(instruction);
                 let value = old_value << 1;
-                self.write_8_bit_value(instruction, value);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_flags_after_shift(value, (old_value & 0b10000000) != 0);
             }
             InstructionType::LSR => {
                 let old_value = self.read_8_bit_value(instruction);
                 let value = old_value >> 1;
-                self.write_8_bit_value(instruction, value);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_flags_after_shift(value, (old_value & 0b00000001) != 0);
             }
             InstructionType::ROL => {
@@ -575,7 +648,7 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 } else {
                     old_value << 1
                 };
-                self.write_8_bit_value(instruction, value);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_flags_after_shift(value, (old_value & 0b10000000) != 0);
             }
             InstructionType::ROR => {
@@ -585,7 +658,7 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 } else {
                     old_value >> 1
                 };
-                self.write_8_bit_value(instruction, value);
+                self.write_modified_value(instruction, old_value, value);
                 self.update_flags_after_shift(value, (old_value & 0b00000001) != 0);
             }
             InstructionType::AND => {
@@ -637,42 +710,42 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
             }
             InstructionType::BCC => {
                 if !self.mmu.cpu().registers.flags().carry {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BCS => {
                 if self.mmu.cpu().registers.flags().carry {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BNE => {
                 if !self.mmu.cpu().registers.flags().zero {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BEQ => {
                 if self.mmu.cpu().registers.flags().zero {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BPL => {
                 if !self.mmu.cpu().registers.flags().negative {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BMI => {
                 if self.mmu.cpu().registers.flags().negative {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BVC => {
                 if !self.mmu.cpu().registers.flags().overflow {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::BVS => {
                 if self.mmu.cpu().registers.flags().overflow {
-                    self.jump(instruction.addressing_mode);
+                    extra_cycles += self.jump(instruction.addressing_mode);
                 }
             }
             InstructionType::TAX => {
@@ -716,12 +789,22 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 self.update_zero_and_negative_flags(value);
             }
             InstructionType::PHP => {
-                let value = self.mmu.cpu().registers.p;
-                Stack::new(self.mmu.cpu_mut()).push(value);
+                // Like BRK, PHP pushes the status with the B flag and bit 5 set
+                let flags = self.mmu.cpu().registers.flags();
+                let value: u8 = Flags {
+                    break_command: true,
+                    ..flags
+                }
+                .into();
+                Stack::new(self.mmu.cpu_mut()).push(value | UNUSED_FLAG_VALUE);
             }
             InstructionType::PLP => {
-                let value = Stack::new(self.mmu.cpu_mut()).pop();
-                self.mmu.cpu_mut().registers.p = value;
+                // As with RTI, the B flag and bit 5 of the pulled byte are ignored
+                let flags = Flags::from(Stack::new(self.mmu.cpu_mut()).pop());
+                self.mmu.cpu_mut().registers.set_flags(Flags {
+                    break_command: false,
+                    ..flags
+                });
             }
             InstructionType::JMP => {
                 let jump_address = self
@@ -759,8 +842,12 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 let lo_word = stack.pop();
                 let hi_word = stack.pop();
 
+                // The B flag only exists on the stack, it is never restored into P
                 let registers = &mut self.mmu.cpu_mut().registers;
-                registers.set_flags(flags);
+                registers.set_flags(Flags {
+                    break_command: false,
+                    ..flags
+                });
                 registers.pc = u16::from_le_bytes([lo_word, hi_word]);
             }
             InstructionType::CLC => {
@@ -813,25 +900,69 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
                 })
             }
             InstructionType::BRK => {
-                let registers = &self.mmu.cpu().registers;
-                let pc_address = registers.pc.to_le_bytes();
-                let flags = registers.flags();
+                // BRK skips its padding byte, so the return address is 2 bytes after the op code
+                let return_address = self.mmu.cpu().registers.pc.wrapping_add(2);
+                self.enter_interrupt(return_address, true, Interrupt::IRQ.vector());
+            }
+            InstructionType::NOP => (),
+        }
 
-                let mut stack = Stack::new(self.mmu.cpu_mut());
-                stack.push(pc_address[1]);
-                stack.push(pc_address[0]);
-                stack.push(flags.into());
+        instruction.cycles() + extra_cycles
+    }
 
-                self.mmu.cpu_mut().registers.set_flags(Flags {
-                    break_command: true,
-                    ..flags
+    /// Runs the interrupt sequence of `interrupt` and returns the number of cycles it took
+    pub fn interrupt(&mut self, interrupt: Interrupt) -> u32 {
+        match interrupt {
+            Interrupt::RESET => {
+                // RESET goes through the same sequence as the other interrupts but the writes to the stack are
+                // suppressed, so only the stack pointer changes
+                let pc = self.read_vector(interrupt.vector());
+                let registers = &mut self.mmu.cpu_mut().registers;
+                registers.s = registers.s.wrapping_sub(3);
+                registers.set_flags(Flags {
+                    interrupt_disable: true,
+                    ..registers.flags()
                 });
+                registers.pc = pc;
+            }
+            Interrupt::NMI | Interrupt::IRQ => {
+                if interrupt == Interrupt::NMI {
+                    self.mmu.cpu_mut().acknowledge_nmi();
+                }
+                let return_address = self.mmu.cpu().registers.pc;
+                self.enter_interrupt(return_address, false, interrupt.vector());
             }
-            InstructionType::NOP => (),
         }
+
+        INTERRUPT_CYCLES
     }
 
-    fn read_8_bit_value(&self, instruction: Instruction) -> u8 {
+    fn enter_interrupt(&mut self, return_address: u16, break_command: bool, vector: u16) {
+        let flags = self.mmu.cpu().registers.flags();
+        let pushed_status: u8 = Flags { break_command, ..flags }.into();
+        let return_address = return_address.to_le_bytes();
+
+        let mut stack = Stack::new(self.mmu.cpu_mut());
+        stack.push(return_address[1]);
+        stack.push(return_address[0]);
+        stack.push(pushed_status | UNUSED_FLAG_VALUE);
+
+        let pc = self.read_vector(vector);
+        let registers = &mut self.mmu.cpu_mut().registers;
+        registers.set_flags(Flags {
+            interrupt_disable: true,
+            ..flags
+        });
+        registers.pc = pc;
+    }
+
+    fn read_vector(&mut self, vector: u16) -> u16 {
+        self.mmu
+            .read_16_bit_value(vector)
+            .expect("Failed to read interrupt vector")
+    }
+
+    fn read_8_bit_value(&mut self, instruction: Instruction) -> u8 {
         self.mmu
             .read_by_mode(instruction.addressing_mode)
             .expect("Failed to read by mode")
@@ -841,6 +972,15 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
         self.mmu.write_8_bit_value_by_mode(instruction.addressing_mode, value);
     }
 
+    /// Read-modify-write instructions write the unmodified value back before the result, which is visible to
+    /// memory mapped registers
+    fn write_modified_value(&mut self, instruction: Instruction, old_value: u8, value: u8) {
+        if instruction.addressing_mode != AddressingMode::Accumulator {
+            self.write_8_bit_value(instruction, old_value);
+        }
+        self.write_8_bit_value(instruction, value);
+    }
+
     fn update_flags_after_arithmetic(&mut self, old_a: u8, value: u8, carry: bool) {
         let registers = &mut self.mmu.cpu_mut().registers;
         let a_sign = Sign::from(registers.a);
@@ -882,13 +1022,24 @@ impl<'a, 'mmu, 'mapped> InstructionExecutor<'a, 'mmu, 'mapped> {
         })
     }
 
-    fn jump(&mut self, addressing_mode: AddressingMode) {
+    fn jump(&mut self, addressing_mode: AddressingMode) -> u32 {
         match addressing_mode {
             AddressingMode::Relative(jump_offset) => {
                 let registers = &mut self.mmu.cpu_mut().registers;
+                let next_instruction = registers.pc.wrapping_add(addressing_mode.byte_length() as u16);
                 registers.pc = match jump_offset.is_positive() {
                     true => registers.pc.wrapping_add(jump_offset as u16),
                     false => registers.pc.wrapping_sub(jump_offset.abs() as u16),
+                };
+
+                // Taken branches cost one extra cycle, and another one if the target is on a different page than
+                // the instruction following the branch
+                match crosses_page(
+                    next_instruction,
+                    registers.pc.wrapping_add(addressing_mode.byte_length() as u16),
+                ) {
+                    true => 2,
+                    false => 1,
                 }
             }
             _ => panic!("Invalid addressing mode for jump instruction. They can only use relative addressing"),
@@ -902,14 +1053,17 @@ pub mod tests {
     use crate::{
         error::InvalidOpCode,
         hardware::{
-            cpu::{AddressingMode, Flags, CPU, MMU},
+            apu::APU,
+            cpu::{AddressingMode, Flags, Interrupt, CPU, MMU},
+            mapper::{Mapper, MMC1, NROM},
             memory::{Memory, Stack},
             ppu::PPU,
         },
+        rom::{Mirroring, PRG_PAGE_SIZE},
     };
 
     fn execute_with_cpu(cpu: &mut CPU, instruction: Instruction) {
-        InstructionExecutor::new(&mut MMU::new(cpu, &mut PPU::new(), None)).execute(instruction);
+        InstructionExecutor::new(&mut MMU::new(cpu, &mut PPU::new(), &mut APU::new(), None)).execute(instruction);
     }
 
     #[test]
@@ -1106,20 +1260,69 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.a = 0x01;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu)
             .execute(Instruction::new(InstructionType::STA, AddressingMode::Absolute(0x0200)));
         assert_eq!(mmu.read(0x0200), Some(0x01));
     }
 
+    #[test]
+    pub fn test_read_modify_write_dummy_write() {
+        // MMC1 ignores the second of two consecutive writes, so it only sees the unmodified value
+        let mut mapper = MMC1::new(vec![0u8; PRG_PAGE_SIZE * 2], vec![], 0, 0);
+        let mut cpu = CPU::new();
+        InstructionExecutor::new(&mut MMU::new(
+            &mut cpu,
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .execute(Instruction::new(InstructionType::INC, AddressingMode::Absolute(0x8000)));
+        for _ in 0..4 {
+            mapper.notify_cpu_cycle();
+            mapper.cpu_write(0x8000, 0x01);
+        }
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    pub fn test_oam_dma() {
+        let mut cpu = CPU::new();
+        cpu.registers.a = 0x02;
+        for (index, value) in cpu.internal_memory[0x0200..0x0300].iter_mut().enumerate() {
+            *value = index as u8;
+        }
+        let mut ppu = PPU::new();
+        let mut apu = APU::new();
+        ppu.registers.oamaddr = 0x04;
+
+        InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut ppu, &mut apu, None))
+            .execute(Instruction::new(InstructionType::STA, AddressingMode::Absolute(0x4014)));
+        assert_eq!(ppu.oam.0[0x04..0x08], [0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(ppu.oam.0[0x00..0x04], [0xFC, 0xFD, 0xFE, 0xFF]);
+        assert_eq!(ppu.registers.oamaddr, 0x04);
+
+        cpu.cycles = 4;
+        assert_eq!(cpu.take_oam_dma_cycles(), 513);
+        assert_eq!(cpu.take_oam_dma_cycles(), 0);
+
+        InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut ppu, &mut apu, None))
+            .execute(Instruction::new(InstructionType::STA, AddressingMode::Absolute(0x4014)));
+        cpu.cycles = 5;
+        assert_eq!(cpu.take_oam_dma_cycles(), 514);
+        assert_eq!(cpu.cycles, 519);
+    }
+
     #[test]
     pub fn test_stx() {
         let mut cpu = CPU::new();
         cpu.registers.x = 0x01;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu)
             .execute(Instruction::new(InstructionType::STX, AddressingMode::Absolute(0x0200)));
         assert_eq!(mmu.read(0x0200), Some(0x01));
@@ -1130,8 +1333,9 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.y = 0x01;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu)
             .execute(Instruction::new(InstructionType::STY, AddressingMode::Absolute(0x0200)));
         assert_eq!(mmu.read(0x0200), Some(0x01));
@@ -1147,7 +1351,10 @@ pub mod tests {
         );
 
         let flags = cpu.registers.flags();
-        assert_eq!(MMU::new(&mut cpu, &mut PPU::new(), None).read(0x0200), Some(0x01));
+        assert_eq!(
+            MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None).read(0x0200),
+            Some(0x01)
+        );
         assert!(!flags.negative);
         assert!(!flags.zero);
     }
@@ -1192,7 +1399,10 @@ pub mod tests {
         );
 
         let flags = cpu.registers.flags();
-        assert_eq!(MMU::new(&mut cpu, &mut PPU::new(), None).read(0x0200), Some(0xFF));
+        assert_eq!(
+            MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None).read(0x0200),
+            Some(0xFF)
+        );
         assert!(flags.negative);
         assert!(!flags.zero);
     }
@@ -1636,8 +1846,9 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.a = 0b11111111;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         mmu.write(0x0000, 0b00000000);
         InstructionExecutor::new(&mut mmu)
             .execute(Instruction::new(InstructionType::BIT, AddressingMode::ZeroPage(0x00)));
@@ -1653,8 +1864,9 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.a = 0b11111111;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         mmu.write(0x0000, 0b11000000);
         InstructionExecutor::new(&mut mmu)
             .execute(Instruction::new(InstructionType::BIT, AddressingMode::ZeroPage(0x00)));
@@ -1879,8 +2091,9 @@ pub mod tests {
         cpu.registers.a = 0x01;
         cpu.registers.s = 0xFF;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu).execute(Instruction::new(InstructionType::PHA, AddressingMode::Implied));
 
         assert_eq!(mmu.cpu().registers.s, 0xFE);
@@ -1892,9 +2105,10 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.s = 0xFF;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
         Stack::new(&mut cpu).push(0x01);
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu).execute(Instruction::new(InstructionType::PLA, AddressingMode::Implied));
 
         let flags = mmu.cpu().registers.flags();
@@ -1910,12 +2124,14 @@ pub mod tests {
         cpu.registers.s = 0xFF;
         cpu.registers.p = 0x01;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu).execute(Instruction::new(InstructionType::PHP, AddressingMode::Implied));
 
         assert_eq!(mmu.cpu().registers.s, 0xFE);
-        assert_eq!(mmu.read(0x01FF), Some(cpu.registers.p));
+        assert_eq!(mmu.read(0x01FF), Some(0x31));
+        assert_eq!(mmu.cpu().registers.p, 0x01);
     }
 
     #[test]
@@ -1923,16 +2139,18 @@ pub mod tests {
         let mut cpu = CPU::new();
         cpu.registers.s = 0xFF;
         let mut ppu = PPU::new();
+        let mut apu = APU::new();
 
-        Stack::new(&mut cpu).push(0x01);
-        let mut mmu = MMU::new(&mut cpu, &mut ppu, None);
+        Stack::new(&mut cpu).push(0xFF);
+        let mut mmu = MMU::new(&mut cpu, &mut ppu, &mut apu, None);
         InstructionExecutor::new(&mut mmu).execute(Instruction::new(InstructionType::PLP, AddressingMode::Implied));
 
         let flags = mmu.cpu().registers.flags();
         assert_eq!(mmu.cpu().registers.s, 0xFF);
-        assert_eq!(mmu.read(0x01FF), Some(cpu.registers.p));
-        assert!(!flags.zero);
-        assert!(!flags.negative);
+        assert_eq!(mmu.cpu().registers.p, 0xCF);
+        assert!(!flags.break_command);
+        assert!(flags.zero);
+        assert!(flags.negative);
     }
 
     #[test]
@@ -2106,6 +2324,11 @@ pub mod tests {
 
     #[test]
     pub fn test_brk() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0x7FFE] = 0x00;
+        prg_rom[0x7FFF] = 0x90;
+        let mut mapper = NROM::new(prg_rom, vec![], 0, 0, Mirroring::Horizontal);
+
         let mut cpu = CPU::new();
         cpu.registers.pc = 0x0600;
         cpu.registers.set_flags(Flags {
@@ -2113,24 +2336,195 @@ pub mod tests {
             ..Default::default()
         });
 
-        execute_with_cpu(
+        let cycles = InstructionExecutor::new(&mut MMU::new(
             &mut cpu,
-            Instruction::new(InstructionType::BRK, AddressingMode::Implied),
-        );
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .execute(Instruction::new(InstructionType::BRK, AddressingMode::Implied));
+
+        assert_eq!(cycles, 7);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert!(cpu.registers.flags().interrupt_disable);
+        assert!(!cpu.registers.flags().break_command);
 
         let mut stack = Stack::new(&mut cpu);
-        assert_eq!(
-            stack.pop(),
-            Flags {
-                carry: true,
-                ..Default::default()
-            }
-            .into()
-        );
+        assert_eq!(stack.pop(), 0b0011_0001);
+        assert_eq!(stack.pop(), 0x02);
+        assert_eq!(stack.pop(), 0x06);
+    }
+
+    #[test]
+    pub fn test_nmi() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0x7FFA] = 0x00;
+        prg_rom[0x7FFB] = 0x80;
+        let mut mapper = NROM::new(prg_rom, vec![], 0, 0, Mirroring::Horizontal);
+
+        let mut cpu = CPU::new();
+        cpu.registers.pc = 0x0600;
+        cpu.registers.set_flags(Flags {
+            interrupt_disable: true,
+            ..Default::default()
+        });
+
+        cpu.set_nmi_line(true);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::NMI));
+
+        InstructionExecutor::new(&mut MMU::new(
+            &mut cpu,
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .interrupt(Interrupt::NMI);
+        assert_eq!(cpu.registers.pc, 0x8000);
+        assert_eq!(cpu.pending_interrupt(), None);
+
+        // Holding the line high doesn't trigger another NMI
+        cpu.set_nmi_line(true);
+        assert_eq!(cpu.pending_interrupt(), None);
+        cpu.set_nmi_line(false);
+        cpu.set_nmi_line(true);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::NMI));
+
+        let mut stack = Stack::new(&mut cpu);
+        assert_eq!(stack.pop(), 0b0010_0100);
         assert_eq!(stack.pop(), 0x00);
         assert_eq!(stack.pop(), 0x06);
-        let flags = cpu.registers.flags();
-        assert!(flags.break_command);
+    }
+
+    #[test]
+    pub fn test_irq() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0x7FFE] = 0x00;
+        prg_rom[0x7FFF] = 0x90;
+        let mut mapper = NROM::new(prg_rom, vec![], 0, 0, Mirroring::Horizontal);
+
+        let mut cpu = CPU::new();
+        cpu.registers.pc = 0x0600;
+        cpu.registers.set_flags(Flags {
+            interrupt_disable: true,
+            ..Default::default()
+        });
+
+        cpu.set_irq_line(true);
+        assert_eq!(cpu.pending_interrupt(), None);
+
+        cpu.registers.set_flags(Default::default());
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::IRQ));
+
+        InstructionExecutor::new(&mut MMU::new(
+            &mut cpu,
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .interrupt(Interrupt::IRQ);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert_eq!(cpu.pending_interrupt(), None);
+
+        InstructionExecutor::new(&mut MMU::new(
+            &mut cpu,
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .execute(Instruction::new(InstructionType::RTI, AddressingMode::Implied));
+        assert_eq!(cpu.registers.pc, 0x0600);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::IRQ));
+    }
+
+    #[test]
+    pub fn test_reset() {
+        let mut prg_rom = vec![0u8; PRG_PAGE_SIZE * 2];
+        prg_rom[0x7FFC] = 0x34;
+        prg_rom[0x7FFD] = 0x12;
+        let mut mapper = NROM::new(prg_rom, vec![], 0, 0, Mirroring::Horizontal);
+
+        let mut cpu = CPU::with_power_up_state();
+        InstructionExecutor::new(&mut MMU::new(
+            &mut cpu,
+            &mut PPU::new(),
+            &mut APU::new(),
+            Some(&mut mapper),
+        ))
+        .interrupt(Interrupt::RESET);
+
+        assert_eq!(cpu.registers.pc, 0x1234);
+        assert_eq!(cpu.registers.s, 0xFD);
+        assert!(cpu.registers.flags().interrupt_disable);
+    }
+
+    #[test]
+    pub fn test_base_cycles() {
+        let mut cpu = CPU::new();
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::LDA, AddressingMode::AbsoluteX(0x0200)),
+        );
+        assert_eq!(cycles, 4);
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::INC, AddressingMode::AbsoluteX(0x0200)),
+        );
+        assert_eq!(cycles, 7);
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::STA, AddressingMode::IndirectIndexed(0x00)),
+        );
+        assert_eq!(cycles, 6);
+    }
+
+    #[test]
+    pub fn test_page_cross_cycles() {
+        let mut cpu = CPU::new();
+        cpu.registers.x = 0x01;
+        cpu.registers.y = 0x10;
+        cpu.internal_memory[0x0010] = 0xF8;
+        cpu.internal_memory[0x0011] = 0x01;
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::LDA, AddressingMode::AbsoluteX(0x02FF)),
+        );
+        assert_eq!(cycles, 5);
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::LDA, AddressingMode::IndirectIndexed(0x10)),
+        );
+        assert_eq!(cycles, 6);
+
+        // Stores always take the worst case cycle count
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None)).execute(
+            Instruction::new(InstructionType::STA, AddressingMode::AbsoluteX(0x02FF)),
+        );
+        assert_eq!(cycles, 5);
+    }
+
+    #[test]
+    pub fn test_branch_cycles() {
+        let mut cpu = CPU::new();
+        cpu.registers.pc = 0x0600;
+        cpu.registers.set_flags(Flags {
+            carry: true,
+            ..Default::default()
+        });
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None))
+            .execute(Instruction::new(InstructionType::BCC, AddressingMode::Relative(0x10)));
+        assert_eq!(cycles, 2);
+        assert_eq!(cpu.registers.pc, 0x0600);
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None))
+            .execute(Instruction::new(InstructionType::BCS, AddressingMode::Relative(0x10)));
+        assert_eq!(cycles, 3);
+        assert_eq!(cpu.registers.pc, 0x0610);
+
+        let cycles = InstructionExecutor::new(&mut MMU::new(&mut cpu, &mut PPU::new(), &mut APU::new(), None))
+            .execute(Instruction::new(InstructionType::BCS, AddressingMode::Relative(-0x20)));
+        assert_eq!(cycles, 4);
+        assert_eq!(cpu.registers.pc, 0x05F0);
     }
 
     #[test]
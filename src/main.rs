@@ -1,52 +1,146 @@
+mod battery;
 mod error;
 mod hardware;
 mod instruction;
+mod player;
 mod rom;
 
+use battery::Battery;
+use error::ROMError;
 use hardware::{
-    cpu::{CPU, MMU as CPUMMU},
-    memory::{MemoryMapper, Memory},
-    ppu::{
-        PPU, MMU as PPUMMU, NameTables, NameTable, PatternTables, State as PPUState, StatusFlags as PPUStatusFlags,
-        PATTERN_TABLE_SECTION_SIZE, NAME_TABLE_SIZE, Tile, TILE_SIZE, PATTERN_TILE_SIZE,
-    },
+    apu::APU,
+    cpu::{Interrupt, CPU, MMU as CPUMMU},
+    mapper::{self, Mapper},
+    memory::Memory,
+    ppu::{Palette, State as PPUState, PPU, PPU_DOTS_PER_CPU_CYCLE, SCREEN_HEIGHT, SCREEN_WIDTH},
 };
 use instruction::{Instruction, InstructionExecutor};
-use rom::{PRG_PAGE_SIZE, ROM};
-use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
+use log::info;
+use player::Player;
+use rom::{archive, fds::Disk, nsf::NSF, patch, unif::UNIF, Timing, ROM};
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    event::Event,
+    keyboard::Keycode,
+    pixels::PixelFormatEnum,
+};
 use simplelog::{Config, LevelFilter, SimpleLogger};
-use std::{env, fs::File, io::Read};
+use std::{
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{self, BufRead, Read},
+    mem,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Battery-backed PRG RAM is flushed to the save file about every 5 seconds
+const SAVE_INTERVAL_FRAMES: u32 = 300;
+const SAMPLE_RATE: u32 = 44_100;
+/// Emulation waits for the audio device once this much audio is queued, which keeps it running at the speed of the console
+const MAX_QUEUED_SAMPLES: u32 = SAMPLE_RATE / 20;
+/// Length of an NTSC frame, which paces emulation when there is no audio device to do it
+const FRAME_DURATION: Duration = Duration::from_nanos(16_639_267);
 
 fn main() {
     SimpleLogger::init(LevelFilter::Debug, Config::default()).unwrap();
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let entry = take_option(&mut args, "--entry");
+    let bios = take_option(&mut args, "--bios");
+    let track = take_option(&mut args, "--track");
 
     let mut rom_file = File::open(&args[1]).unwrap();
     let mut buffer = Vec::new();
     rom_file
         .read_to_end(&mut buffer)
         .expect("Failed to read ROM file to end");
-    let rom = ROM::with_content(buffer).unwrap();
-    let mapper = MemoryMapper::NROM(&rom.prg_rom()[0x0000..PRG_PAGE_SIZE], &rom.prg_rom()[PRG_PAGE_SIZE..]);
+    buffer = match archive::extract(buffer, entry.as_deref()) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    if let Some(patch_path) = patch_path(&args) {
+        let patch = match fs::read(&patch_path) {
+            Ok(patch) => patch,
+            Err(err) => {
+                println!("Failed to read {}. {}", patch_path.display(), err);
+                return;
+            }
+        };
+        buffer = match patch::apply(&buffer, &patch) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                println!("Failed to apply {}. {}", patch_path.display(), err);
+                return;
+            }
+        };
+        info!("Applied {}", patch_path.display());
+    }
+    if NSF::is_nsf(&buffer) {
+        if let Err(err) = play_nsf(&buffer, track.as_deref()) {
+            println!("{}", err);
+        }
+        return;
+    }
+    let loaded = if Disk::is_disk_image(&buffer) {
+        let bios_path = bios.map_or_else(|| Path::new(&args[1]).with_file_name("disksys.rom"), PathBuf::from);
+        load_disk(&buffer, &bios_path).map(|mapper| (mapper, true))
+    } else if UNIF::is_unif(&buffer) {
+        load_unif(&buffer).map_err(Into::into)
+    } else {
+        load_rom(buffer).map_err(Into::into)
+    };
+    let (mut mapper, has_save_data) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let mut battery = has_save_data.then(|| Battery::new(Path::new(&args[1])));
+    if let Some(battery) = battery.as_mut() {
+        if let Err(err) = battery.load(mapper.as_mut()) {
+            println!("Failed to load save file. {}", err);
+        }
+    }
 
     let mut cpu = CPU::with_power_up_state();
-    cpu.registers.pc = u16::from_le_bytes([mapper.read(0xFFFC).unwrap(), mapper.read(0xFFFD).unwrap()]);
-
     let mut ppu = PPU::new();
-    
+    // Only the NTSC PPU is emulated, so the APU runs at the NTSC rate too
+    let mut apu = APU::with_sample_rate(Timing::NTSC, SAMPLE_RATE);
+    InstructionExecutor::new(&mut CPUMMU::new(&mut cpu, &mut ppu, &mut apu, Some(mapper.as_mut())))
+        .interrupt(Interrupt::RESET);
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let rect_scale: i32 = 3;
+    let scale = 3;
     let window = video_subsystem
-        .window("dam4nes", (256 * rect_scale + 30) as u32, (256 * rect_scale + 30) as u32)
+        .window("dam4nes", (SCREEN_WIDTH * scale) as u32, (SCREEN_HEIGHT * scale) as u32)
         .position_centered()
         .build()
         .unwrap();
 
     let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .unwrap();
+    let audio_queue = match open_audio_queue(&sdl_context) {
+        Ok(audio_queue) => Some(audio_queue),
+        Err(err) => {
+            println!("Failed to open the audio device, running without sound. {}", err);
+            None
+        }
+    };
+    let mut next_frame = Instant::now();
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut bitmap = [[0u8; 256]; 256];
+    let mut pixels = [0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+    let mut frames = 0u32;
 
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -56,96 +150,212 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => mapper.switch_disk_side(),
                 _ => (),
             }
         }
 
-        match Instruction::from_machine_code(mapper.slice_from(cpu.registers.pc).unwrap()) {
-            Ok(Some(instruction)) => {
-                InstructionExecutor::new(&mut CPUMMU::new(&mut cpu, &mut ppu, Some(&mapper))).execute(instruction);
-                if instruction.instruction_type.increments_pc() {
-                    cpu.registers.pc += instruction.addressing_mode.byte_length() as u16;
-                }
+        let cycles = match cpu.pending_interrupt() {
+            Some(interrupt) => {
+                InstructionExecutor::new(&mut CPUMMU::new(&mut cpu, &mut ppu, &mut apu, Some(mapper.as_mut())))
+                    .interrupt(interrupt)
             }
-            Ok(None) => break 'running,
-            Err(err) => {
-                println!("Error at offset {:#X}. {}", cpu.registers.pc, err);
-                break 'running;
+            None => {
+                let mut mmu = CPUMMU::new(&mut cpu, &mut ppu, &mut apu, Some(mapper.as_mut()));
+                let pc = mmu.cpu().registers.pc;
+                let machine_code: Vec<u8> = (0..3).map_while(|offset| mmu.read(pc.wrapping_add(offset))).collect();
+                match Instruction::from_machine_code(&machine_code) {
+                    Ok(Some(instruction)) => {
+                        let cycles = InstructionExecutor::new(&mut mmu).execute(instruction);
+                        if instruction.instruction_type.increments_pc() {
+                            mmu.cpu_mut().registers.pc += instruction.addressing_mode.byte_length() as u16;
+                        }
+                        cycles
+                    }
+                    Ok(None) => break 'running,
+                    Err(err) => {
+                        println!("Error at offset {:#X}. {}", pc, err);
+                        break 'running;
+                    }
+                }
             }
-        }
+        };
+        cpu.cycles += cycles as u64;
+        let mut cycles = cycles + cpu.take_oam_dma_cycles();
 
-        ppu.clock.step();
-        match ppu.state() {
-            Some(PPUState::VBlankToggle(true)) => {
-                let status_flags = ppu.registers.status_flags();
-                ppu.registers.set_status_flags(PPUStatusFlags {
-                    vblank: true,
-                    ..status_flags
-                });
-
-                canvas.set_draw_color(Color::RGB(255, 255, 255));
-                canvas.clear();
-
-                for (y, row) in bitmap.iter().enumerate() {
-                    for (x, pixel) in row.iter().enumerate() {
-                        canvas.set_draw_color(match pixel {
-                            0 => Color::RGB(0, 0, 0),
-                            1 => Color::RGB(255, 0, 0),
-                            2 => Color::RGB(0, 255, 0),
-                            3 => Color::RGB(0, 0, 255),
-                            _ => Color::RGB(255, 255, 255),
-                        });
-                        canvas.fill_rect(Rect::new(
-                            x as i32 * rect_scale,
-                            y as i32 * rect_scale,
-                            rect_scale as u32,
-                            rect_scale as u32,
-                        )).unwrap();
+        while cycles > 0 {
+            cycles -= 1;
+            mapper.notify_cpu_cycle();
+            apu.step();
+            // DMC sample fetches steal cycles from the CPU, during which the rest of the console keeps running
+            cycles += CPUMMU::new(&mut cpu, &mut ppu, &mut apu, Some(mapper.as_mut())).dmc_dma();
+            for _ in 0..PPU_DOTS_PER_CPU_CYCLE {
+                if let Some(PPUState::VBlankToggle(true)) = ppu.step(Some(mapper.as_mut())) {
+                    for (pixel, palette_index) in pixels.chunks_exact_mut(3).zip(ppu.framebuffer.iter().flatten()) {
+                        let color = Palette::color(*palette_index);
+                        pixel.copy_from_slice(&[color.r, color.g, color.b]);
+                    }
+                    texture.update(None, &pixels, SCREEN_WIDTH * 3).unwrap();
+                    canvas.copy(&texture, None, None).unwrap();
+                    canvas.present();
+                    let samples = apu.take_samples();
+                    match audio_queue.as_ref() {
+                        Some(audio_queue) => queue_samples(audio_queue, &samples),
+                        None => wait_for_frame(&mut next_frame),
                     }
-                }
 
-                canvas.present();
+                    frames += 1;
+                    if frames == SAVE_INTERVAL_FRAMES {
+                        frames = 0;
+                        flush_battery(battery.as_mut(), mapper.as_ref());
+                    }
+                }
+                cpu.set_nmi_line(ppu.nmi_line());
             }
-            Some(PPUState::VBlankToggle(false)) => {
-                let status_flags = ppu.registers.status_flags();
-                ppu.registers.set_status_flags(PPUStatusFlags {
-                    vblank: false,
-                    ..status_flags
-                });
+            cpu.set_irq_line(mapper.irq() || apu.irq());
+        }
+    }
+    flush_battery(battery.as_mut(), mapper.as_ref());
+}
+
+/// Builds the mapper of an iNES or NES 2.0 file, and tells whether it has battery-backed memory
+fn load_rom(content: Vec<u8>) -> Result<(Box<dyn Mapper>, bool), ROMError> {
+    let rom = ROM::with_content(content)?;
+    if let Some(title) = rom.title() {
+        info!("{}", title);
+    }
+    if rom.header_overridden() {
+        info!("Header corrected by the ROM database");
+    }
+    info!(
+        "Mapper {}.{}, {:?} {:?} timing, {:?} expansion device",
+        rom.mapper_number(),
+        rom.submapper_number(),
+        rom.console_type(),
+        rom.timing(),
+        rom.default_expansion_device()
+    );
+    info!(
+        "PRG RAM {} + {} battery-backed bytes, CHR RAM {} + {} battery-backed bytes",
+        rom.prg_ram_size(),
+        rom.prg_nvram_size(),
+        rom.chr_ram_size(),
+        rom.chr_nvram_size()
+    );
+    Ok((mapper::from_rom(&rom)?, rom.has_battery()))
+}
+
+/// Builds the mapper of a UNIF file from its board name, and tells whether it has battery-backed memory
+fn load_unif(content: &[u8]) -> Result<(Box<dyn Mapper>, bool), ROMError> {
+    let unif = UNIF::with_content(content)?;
+    info!("UNIF board {}", unif.board());
+    Ok((mapper::from_unif(&unif)?, unif.has_battery()))
+}
+
+/// Builds the FDS RAM adapter for a disk image, which boots from the BIOS of the Famicom Disk System
+fn load_disk(content: &[u8], bios_path: &Path) -> Result<Box<dyn Mapper>, Box<dyn Error>> {
+    let disk = Disk::with_content(content)?;
+    let bios = fs::read(bios_path).map_err(|err| format!("Failed to read {}. {}", bios_path.display(), err))?;
+    info!("FDS disk with {} sides", disk.sides().len());
+    Ok(mapper::from_disk(bios, &disk)?)
+}
+
+/// Plays NSF music without opening a window, starting at `track` counted from 1
+fn play_nsf(content: &[u8], track: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let nsf = NSF::with_content(content)?;
+    info!("{} by {}, {}", nsf.title(), nsf.artist(), nsf.copyright());
+    if nsf.extra_sound_chips() != 0 {
+        info!("Expansion audio {:#04X} isn't supported", nsf.extra_sound_chips());
+    }
+    let mut player = Player::new(nsf, SAMPLE_RATE);
+    if let Some(track) = track {
+        player.select_song(track.parse::<u8>()?.saturating_sub(1));
+    }
+    info!("Song {} of {}", player.song() + 1, player.nsf().song_count());
+    info!("Enter n or p for the next or previous song, or the number of a song");
+
+    // Stdin is read on its own thread, as there is no window to deliver key presses
+    let (sender, commands) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
             }
-            Some(PPUState::RenderTile{ x, y }) => {
-                let mut chr_rom_chunks = rom.chr_rom().chunks_exact(PATTERN_TABLE_SECTION_SIZE);
-                let pattern_tables = PatternTables::new(
-                    chr_rom_chunks.next().unwrap(),
-                    chr_rom_chunks.next().unwrap(),
-                ).unwrap();
-
-                let top_left_name_table = NameTable::with_slice(&ppu.internal_memory[..NAME_TABLE_SIZE]).unwrap();
-                let top_right_name_table = NameTable::with_slice(
-                    &ppu.internal_memory[NAME_TABLE_SIZE..(NAME_TABLE_SIZE * 2)]
-                ).unwrap();
-
-                let mmu = PPUMMU {
-                    pattern_tables,
-                    name_tables: NameTables::new(
-                        top_left_name_table.clone(),
-                        top_right_name_table.clone(),
-                        top_left_name_table,
-                        top_right_name_table,
-                    )
-                };
-
-                let start_address = (((y / TILE_SIZE) * 32) + (x / TILE_SIZE)) * PATTERN_TILE_SIZE as u32;
-                let pattern_tile_bytes = (start_address..start_address + 16)
-                    .map(|address| mmu.read(address as u16).unwrap())
-                    .collect::<Vec<_>>();
-
-                let tile = Tile::from_pattern_table_slice(pattern_tile_bytes.as_slice()).unwrap();
-                for (tile_row, out_row) in tile.0.iter().zip(&mut bitmap[y as usize..]) {
-                    out_row[x as usize..x as usize + 8].copy_from_slice(tile_row);
-                }
+        }
+    });
+    let sdl_context = sdl2::init()?;
+    let audio_queue = open_audio_queue(&sdl_context)?;
+    loop {
+        if let Ok(command) = commands.try_recv() {
+            match command.trim() {
+                "n" => player.next_song(),
+                "p" => player.previous_song(),
+                song => match song.parse::<u8>() {
+                    Ok(song) => player.select_song(song.saturating_sub(1)),
+                    Err(_) => continue,
+                },
             }
-            None => (),
+            info!("Song {} of {}", player.song() + 1, player.nsf().song_count());
+            // The previous song shouldn't keep playing from the queue
+            audio_queue.clear();
         }
+        player.run_period()?;
+        queue_samples(&audio_queue, &player.take_samples());
+    }
+}
+
+fn open_audio_queue(sdl_context: &sdl2::Sdl) -> Result<AudioQueue<f32>, String> {
+    let desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio_queue = sdl_context.audio()?.open_queue(None, &desired)?;
+    audio_queue.resume();
+    Ok(audio_queue)
+}
+
+/// Queues the samples, then waits until the audio device has played enough of the queue
+fn queue_samples(audio_queue: &AudioQueue<f32>, samples: &[f32]) {
+    audio_queue.queue(samples);
+    while audio_queue.size() > MAX_QUEUED_SAMPLES * mem::size_of::<f32>() as u32 {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Sleeps until `next_frame`, then moves it one frame later. Time lost to a slow frame isn't made up for
+fn wait_for_frame(next_frame: &mut Instant) {
+    let now = Instant::now();
+    if *next_frame > now {
+        thread::sleep(*next_frame - now);
+    }
+    *next_frame = (*next_frame).max(now) + FRAME_DURATION;
+}
+
+/// Removes `name` and the value following it from the command line arguments
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    let value = args.get(index + 1).cloned();
+    args.drain(index..(index + 2).min(args.len()));
+    value
+}
+
+/// The patch passed after the ROM, or an IPS, UPS or BPS file next to it with the same name
+fn patch_path(args: &[String]) -> Option<PathBuf> {
+    match args.get(2) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => ["ips", "ups", "bps"]
+            .iter()
+            .map(|extension| Path::new(&args[1]).with_extension(extension))
+            .find(|path| path.exists()),
+    }
+}
+
+fn flush_battery(battery: Option<&mut Battery>, mapper: &dyn Mapper) {
+    if let Some(Err(err)) = battery.map(|battery| battery.flush(mapper)) {
+        println!("Failed to write save file. {}", err);
     }
 }
@@ -0,0 +1,236 @@
+use crate::{
+    error::InvalidOpCode,
+    hardware::{
+        apu::{APU, NTSC_CPU_CLOCK, PAL_CPU_CLOCK},
+        cpu::{CPU, MMU},
+        mapper::{Mapper, NSFCartridge},
+        memory::{Memory, Stack},
+        ppu::PPU,
+    },
+    instruction::{Instruction, InstructionExecutor},
+    rom::{nsf::NSF, Timing},
+};
+
+/// INIT and PLAY are called as subroutines that return to this address. It is in the unused $4018-$401F range,
+/// so no music code can run there
+const RETURN_ADDRESS: u16 = 0x4018;
+
+/// Plays NSF music on the emulated CPU, calling the INIT routine when a song is selected and then the PLAY
+/// routine at the rate of the file. The PPU only completes the address space, it never renders
+pub struct Player {
+    nsf: NSF,
+    cpu: CPU,
+    ppu: PPU,
+    apu: APU,
+    cartridge: NSFCartridge,
+    song: u8,
+}
+
+impl Player {
+    /// `sample_rate` is the number of samples per second that `take_samples` returns
+    pub fn new(nsf: NSF, sample_rate: u32) -> Self {
+        let song = nsf.starting_song();
+        let mut player = Self {
+            cartridge: NSFCartridge::new(&nsf),
+            apu: APU::with_sample_rate(nsf.timing(), sample_rate),
+            nsf,
+            cpu: CPU::with_power_up_state(),
+            ppu: PPU::new(),
+            song,
+        };
+        player.select_song(song);
+        player
+    }
+
+    pub fn nsf(&self) -> &NSF {
+        &self.nsf
+    }
+
+    /// Index of the playing song, counted from 0
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+
+    /// Clears the memory and the sound registers like the console would be after power up, and calls INIT for `song`
+    pub fn select_song(&mut self, song: u8) {
+        self.song = song.min(self.nsf.song_count().saturating_sub(1));
+        self.cpu.internal_memory.fill(0);
+        if let Some(prg_ram) = self.cartridge.prg_ram_mut() {
+            prg_ram.fill(0);
+        }
+
+        let bank_switching = self.nsf.bank_switching();
+        let mut mmu = self.mmu();
+        for address in 0x4000..=0x4013 {
+            mmu.write(address, 0x00);
+        }
+        mmu.write(0x4015, 0x00);
+        mmu.write(0x4015, 0x0F);
+        mmu.write(0x4017, 0x40);
+        if let Some(banks) = bank_switching {
+            for (address, bank) in (0x5FF8..=0x5FFF).zip(banks.iter()) {
+                mmu.write(address, *bank);
+            }
+        }
+
+        // INIT tells the regions apart by X
+        let region = match self.is_pal() {
+            false => 0,
+            true => 1,
+        };
+        let registers = &mut self.cpu.registers;
+        registers.a = self.song;
+        registers.x = region;
+        registers.y = 0;
+        registers.s = 0xFD;
+        registers.p = 0x04;
+        self.call(self.nsf.init_address());
+    }
+
+    pub fn next_song(&mut self) {
+        match self.song + 1 < self.nsf.song_count() {
+            true => self.select_song(self.song + 1),
+            false => self.select_song(0),
+        }
+    }
+
+    pub fn previous_song(&mut self) {
+        match self.song {
+            0 => self.select_song(self.nsf.song_count().saturating_sub(1)),
+            song => self.select_song(song - 1),
+        }
+    }
+
+    /// Runs the CPU for one play period, calling PLAY first unless INIT or the previous PLAY is still running
+    pub fn run_period(&mut self) -> Result<(), InvalidOpCode> {
+        if self.cpu.registers.pc == RETURN_ADDRESS {
+            self.call(self.nsf.play_address());
+        }
+        let clock = match self.is_pal() {
+            false => NTSC_CPU_CLOCK,
+            true => PAL_CPU_CLOCK,
+        };
+        let period_cycles = self.speed() as u64 * clock as u64 / 1_000_000;
+        let mut cycles = 0;
+        while cycles < period_cycles && self.cpu.registers.pc != RETURN_ADDRESS {
+            cycles += self.step()? as u64;
+        }
+        // The CPU idles for the rest of the period, while the APU keeps playing
+        for _ in cycles..period_cycles {
+            self.apu.step();
+            self.mmu().dmc_dma();
+        }
+        self.cpu.cycles += period_cycles.saturating_sub(cycles);
+        Ok(())
+    }
+
+    /// Audio samples produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
+    fn is_pal(&self) -> bool {
+        self.nsf.timing() == Timing::PAL
+    }
+
+    /// Microseconds between two calls of PLAY
+    fn speed(&self) -> u16 {
+        match self.is_pal() {
+            false => self.nsf.ntsc_speed(),
+            true => self.nsf.pal_speed(),
+        }
+    }
+
+    fn mmu(&mut self) -> MMU<'_> {
+        MMU::new(&mut self.cpu, &mut self.ppu, &mut self.apu, Some(&mut self.cartridge))
+    }
+
+    /// Jumps to `address` as if a JSR at RETURN_ADDRESS called it
+    fn call(&mut self, address: u16) {
+        let [low, high] = (RETURN_ADDRESS - 1).to_le_bytes();
+        let mut stack = Stack::new(&mut self.cpu);
+        stack.push(high);
+        stack.push(low);
+        self.cpu.registers.pc = address;
+    }
+
+    fn step(&mut self) -> Result<u32, InvalidOpCode> {
+        let mut mmu = self.mmu();
+        let pc = mmu.cpu().registers.pc;
+        let machine_code: Vec<u8> = (0..3).map_while(|offset| mmu.read(pc.wrapping_add(offset))).collect();
+        let cycles = match Instruction::from_machine_code(&machine_code)? {
+            Some(instruction) => {
+                let cycles = InstructionExecutor::new(&mut mmu).execute(instruction);
+                if instruction.instruction_type.increments_pc() {
+                    mmu.cpu_mut().registers.pc += instruction.addressing_mode.byte_length() as u16;
+                }
+                cycles
+            }
+            // Code running into unmapped memory can't go on, so the routine is given up
+            None => {
+                mmu.cpu_mut().registers.pc = RETURN_ADDRESS;
+                0
+            }
+        };
+        self.cpu.cycles += cycles as u64;
+
+        // DMC sample fetches halt the CPU, which makes the instruction take longer
+        let mut remaining = cycles;
+        let mut cycles = cycles;
+        while remaining > 0 {
+            remaining -= 1;
+            self.apu.step();
+            let halted = self.mmu().dmc_dma();
+            remaining += halted;
+            cycles += halted;
+        }
+        Ok(cycles)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Player, RETURN_ADDRESS};
+    use crate::rom::nsf::NSF;
+
+    fn nsf(song_count: u8) -> NSF {
+        let mut content = b"NESM\x1A\x01".to_vec();
+        content.extend(&[song_count, 0x01, 0x00, 0x80, 0x00, 0x80, 0x04, 0x80]);
+        content.resize(0x80, 0x00);
+        // INIT stores the song at $0200 and PLAY counts its calls at $0201
+        content.extend(&[0x8D, 0x00, 0x02, 0x60]);
+        content.extend(&[0xEE, 0x01, 0x02, 0x60]);
+        NSF::with_content(&content).unwrap()
+    }
+
+    #[test]
+    pub fn test_player() {
+        let mut player = Player::new(nsf(3), 44_100);
+        assert_eq!(player.song(), 0);
+        player.run_period().unwrap();
+        // A period at the default NTSC rate lasts 29779 CPU cycles
+        assert_eq!(player.take_samples().len(), 733);
+        assert_eq!(player.cpu.registers.pc, RETURN_ADDRESS);
+        assert_eq!(player.cpu.internal_memory[0x0201], 0x00);
+        player.run_period().unwrap();
+        player.run_period().unwrap();
+        assert_eq!(player.cpu.internal_memory[0x0201], 0x02);
+        assert_eq!(player.cpu.registers.s, 0xFD);
+
+        player.select_song(2);
+        player.run_period().unwrap();
+        assert_eq!(player.cpu.internal_memory[0x0200], 0x02);
+        assert_eq!(player.cpu.internal_memory[0x0201], 0x00);
+    }
+
+    #[test]
+    pub fn test_song_selection() {
+        let mut player = Player::new(nsf(3), 44_100);
+        player.previous_song();
+        assert_eq!(player.song(), 2);
+        player.next_song();
+        assert_eq!(player.song(), 0);
+        player.select_song(5);
+        assert_eq!(player.song(), 2);
+    }
+}
@@ -1,70 +1,581 @@
+pub mod archive;
+mod checksum;
+mod database;
+pub mod fds;
+pub mod nsf;
+pub mod patch;
+pub mod unif;
+
+use crate::error::ROMError;
+use database::Game;
+
 pub(crate) const PRG_PAGE_SIZE: usize = 16 * 1024;
 pub(crate) const CRH_PAGE_SIZE: usize = 8 * 1024;
+pub(crate) const PRG_RAM_PAGE_SIZE: usize = 8 * 1024;
+
+const HEADER_SIZE: usize = 0x10;
+pub(crate) const TRAINER_SIZE: usize = 0x200;
 
 #[derive(Debug, Default)]
 pub struct ROM {
     content: Vec<u8>,
-    prg_rom_page_count: u8,
-    chr_rom_page_count: u8,
+    prg_rom_size: usize,
+    chr_rom_size: usize,
     flags_6: u8,
     flags_7: u8,
-    prg_ram_page_count: u8,
+    flags_8: u8,
     flags_9: u8,
     flags_10: u8,
+    flags_11: u8,
+    flags_12: u8,
+    flags_13: u8,
+    flags_15: u8,
+    game: Option<&'static Game>,
 }
 
 impl ROM {
-    pub fn with_content(content: Vec<u8>) -> Result<Self, &'static str> {
-        match content.as_slice() {
-            [0x4E, 0x45, 0x53, 0x1A, prg_rom_page_count, chr_rom_page_count, flags_6, flags_7, prg_ram_page_count, flags_9, flags_10, 0x00, 0x00, 0x00, 0x00, 0x00, ..] =>
+    pub fn with_content(content: Vec<u8>) -> Result<Self, ROMError> {
+        Self::with_database(content, database::games())
+    }
+
+    fn with_database(content: Vec<u8>, games: &'static [Game]) -> Result<Self, ROMError> {
+        let (prg_rom_page_count, chr_rom_page_count, mut rom) = match content.as_slice() {
+            [0x4E, 0x45, 0x53, 0x1A, prg_rom_page_count, chr_rom_page_count, flags_6, flags_7, flags_8, flags_9, flags_10, flags_11, flags_12, flags_13, _, flags_15, ..]
+                if Self::is_nes_2_0_header(*flags_7) =>
             {
-                let prg_rom_page_count = *prg_rom_page_count;
-                let chr_rom_page_count = *chr_rom_page_count;
-                let flags_6 = *flags_6;
-                let flags_7 = *flags_7;
-                let prg_ram_page_count = *prg_ram_page_count;
-                let flags_9 = *flags_9;
-                let flags_10 = *flags_10;
-                Ok(Self {
-                    content,
-                    prg_rom_page_count,
-                    chr_rom_page_count,
-                    flags_6,
-                    flags_7,
-                    prg_ram_page_count,
-                    flags_9,
-                    flags_10,
-                })
+                (
+                    *prg_rom_page_count,
+                    *chr_rom_page_count,
+                    Self {
+                        flags_6: *flags_6,
+                        flags_7: *flags_7,
+                        flags_8: *flags_8,
+                        flags_9: *flags_9,
+                        flags_10: *flags_10,
+                        flags_11: *flags_11,
+                        flags_12: *flags_12,
+                        flags_13: *flags_13,
+                        flags_15: *flags_15,
+                        ..Self::default()
+                    },
+                )
+            }
+            [0x4E, 0x45, 0x53, 0x1A, prg_rom_page_count, chr_rom_page_count, flags_6, flags_7, prg_ram_page_count, flags_9, flags_10, 0x00, 0x00, 0x00, 0x00, 0x00, ..] => {
+                (
+                    *prg_rom_page_count,
+                    *chr_rom_page_count,
+                    Self {
+                        flags_6: *flags_6,
+                        flags_7: *flags_7,
+                        flags_8: *prg_ram_page_count,
+                        flags_9: *flags_9,
+                        flags_10: *flags_10,
+                        ..Self::default()
+                    },
+                )
             }
-            _ => Err("Input is not a valid iNES file format"),
+            [0x4E, 0x45, 0x53, 0x1A, _, _, _, _, _, _, _, _, _, _, _, _, ..] => return Err(ROMError::DirtyHeader),
+            [0x4E, 0x45, 0x53, 0x1A, ..] => return Err(ROMError::TruncatedHeader),
+            _ => return Err(ROMError::InvalidMagic),
+        };
+
+        rom.prg_rom_size = match rom.is_nes_2_0() {
+            false => prg_rom_page_count as usize * PRG_PAGE_SIZE,
+            true => Self::rom_size(prg_rom_page_count, rom.flags_9 & 0x0F, PRG_PAGE_SIZE)
+                .ok_or(ROMError::InvalidPRGROMSize)?,
+        };
+        rom.chr_rom_size = match rom.is_nes_2_0() {
+            false => chr_rom_page_count as usize * CRH_PAGE_SIZE,
+            true => Self::rom_size(chr_rom_page_count, rom.flags_9 >> 4, CRH_PAGE_SIZE)
+                .ok_or(ROMError::InvalidCHRROMSize)?,
+        };
+
+        let trainer_size = match rom.has_trainer() {
+            false => 0,
+            true => TRAINER_SIZE,
+        };
+        if content.len() < HEADER_SIZE + trainer_size {
+            return Err(ROMError::TruncatedTrainer);
+        }
+        let prg_rom_available = content.len() - HEADER_SIZE - trainer_size;
+        if prg_rom_available < rom.prg_rom_size {
+            return Err(ROMError::TruncatedPRGROM {
+                expected: rom.prg_rom_size,
+                actual: prg_rom_available,
+            });
         }
+        let chr_rom_available = prg_rom_available - rom.prg_rom_size;
+        if chr_rom_available < rom.chr_rom_size {
+            return Err(ROMError::TruncatedCHRROM {
+                expected: rom.chr_rom_size,
+                actual: chr_rom_available,
+            });
+        }
+
+        rom.content = content;
+        let prg_and_chr_rom = [rom.prg_rom(), rom.chr_rom()].concat();
+        rom.game = database::find(
+            games,
+            checksum::crc32(&prg_and_chr_rom),
+            &checksum::sha1(&prg_and_chr_rom),
+        );
+        Ok(rom)
+    }
+
+    fn is_nes_2_0_header(flags_7: u8) -> bool {
+        flags_7 & 0x0C == 0x08
+    }
+
+    pub fn is_nes_2_0(&self) -> bool {
+        Self::is_nes_2_0_header(self.flags_7)
+    }
+
+    /// Title of the game, when the dump is in the ROM database
+    pub fn title(&self) -> Option<&'static str> {
+        self.game.map(|game| game.title)
+    }
+
+    /// Whether the ROM database corrected any field of a wrong header
+    pub fn header_overridden(&self) -> bool {
+        self.game.is_some_and(|game| {
+            game.mapper_number != self.header_mapper_number()
+                || game.submapper_number != self.header_submapper_number()
+                || game.mirroring != self.header_mirroring()
+                || game.has_battery != self.header_has_battery()
+        })
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.game.map_or_else(|| self.header_mirroring(), |game| game.mirroring)
     }
 
-    // pub fn mirroring(&self) -> Mirroring {
-    //     match (self.flags_6 & 0x01) != 0 {
-    //         false => Mirroring::Horizontal,
-    //         true => Mirroring::Vertical,
-    //     }
-    // }
+    pub fn has_battery(&self) -> bool {
+        self.game
+            .map_or_else(|| self.header_has_battery(), |game| game.has_battery)
+    }
+
+    pub fn mapper_number(&self) -> u16 {
+        self.game
+            .map_or_else(|| self.header_mapper_number(), |game| game.mapper_number)
+    }
+
+    pub fn submapper_number(&self) -> u8 {
+        self.game
+            .map_or_else(|| self.header_submapper_number(), |game| game.submapper_number)
+    }
+
+    fn header_mirroring(&self) -> Mirroring {
+        match (self.flags_6 & 0x08 != 0, self.flags_6 & 0x01 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, false) => Mirroring::Horizontal,
+            (false, true) => Mirroring::Vertical,
+        }
+    }
+
+    fn header_has_battery(&self) -> bool {
+        self.flags_6 & 0x02 != 0
+    }
+
+    /// 8 bit in iNES, extended to 12 bits by NES 2.0
+    fn header_mapper_number(&self) -> u16 {
+        let mapper_number = ((self.flags_7 & 0xF0) | (self.flags_6 >> 4)) as u16;
+        match self.is_nes_2_0() {
+            false => mapper_number,
+            true => ((self.flags_8 & 0x0F) as u16) << 8 | mapper_number,
+        }
+    }
+
+    /// Board variant of the mapper, 0 when unspecified
+    fn header_submapper_number(&self) -> u8 {
+        match self.is_nes_2_0() {
+            false => 0,
+            true => self.flags_8 >> 4,
+        }
+    }
+
+    /// Volatile PRG RAM. iNES reads a PRG RAM page count of 0 as 8 KiB for compatibility
+    pub fn prg_ram_size(&self) -> usize {
+        match self.is_nes_2_0() {
+            false => self.flags_8.max(1) as usize * PRG_RAM_PAGE_SIZE,
+            true => Self::shift_size(self.flags_10),
+        }
+    }
+
+    /// Battery-backed PRG RAM, only known for NES 2.0 headers
+    pub fn prg_nvram_size(&self) -> usize {
+        match self.is_nes_2_0() {
+            false => 0,
+            true => Self::shift_size(self.flags_10 >> 4),
+        }
+    }
+
+    /// iNES boards without CHR ROM have 8 KiB of CHR RAM
+    pub fn chr_ram_size(&self) -> usize {
+        match (self.is_nes_2_0(), self.chr_rom_size) {
+            (false, 0) => CRH_PAGE_SIZE,
+            (false, _) => 0,
+            (true, _) => Self::shift_size(self.flags_11),
+        }
+    }
+
+    pub fn chr_nvram_size(&self) -> usize {
+        match self.is_nes_2_0() {
+            false => 0,
+            true => Self::shift_size(self.flags_11 >> 4),
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        match (self.is_nes_2_0(), self.flags_12 & 0x03) {
+            (false, _) | (true, 0) => Timing::NTSC,
+            (true, 1) => Timing::PAL,
+            (true, 2) => Timing::MultipleRegion,
+            (true, _) => Timing::Dendy,
+        }
+    }
+
+    pub fn console_type(&self) -> ConsoleType {
+        match self.flags_7 & 0x03 {
+            0 => ConsoleType::NES,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::PlayChoice10,
+            _ => match self.is_nes_2_0() {
+                false => ConsoleType::Extended(0),
+                true => ConsoleType::Extended(self.flags_13 & 0x0F),
+            },
+        }
+    }
+
+    pub fn default_expansion_device(&self) -> ExpansionDevice {
+        match self.is_nes_2_0() {
+            false => ExpansionDevice::Unspecified,
+            true => ExpansionDevice::from(self.flags_15 & 0x3F),
+        }
+    }
+
+    pub fn has_trainer(&self) -> bool {
+        self.flags_6 & 0x04 != 0
+    }
+
+    /// Code that copiers loaded into PRG RAM at $7000-$71FF
+    pub fn trainer(&self) -> Option<&[u8]> {
+        match self.has_trainer() {
+            false => None,
+            true => Some(&self.content[HEADER_SIZE..HEADER_SIZE + TRAINER_SIZE]),
+        }
+    }
 
     pub fn prg_rom(&self) -> &[u8] {
-        &self.content[0x10..0x10 + self.prg_rom_size()]
+        let start = self.prg_rom_offset();
+        &self.content[start..start + self.prg_rom_size]
     }
 
     pub fn chr_rom(&self) -> &[u8] {
-        &self.content[0x10 + self.prg_rom_size()..0x10 + self.prg_rom_size() + self.chr_rom_size()]
+        let start = self.prg_rom_offset() + self.prg_rom_size;
+        &self.content[start..start + self.chr_rom_size]
+    }
+
+    fn prg_rom_offset(&self) -> usize {
+        HEADER_SIZE + self.trainer().map_or(0, <[u8]>::len)
     }
 
-    fn prg_rom_size(&self) -> usize {
-        self.prg_rom_page_count as usize * PRG_PAGE_SIZE
+    /// NES 2.0 ROM sizes are either a 12 bit page count, or 2^E * (MM * 2 + 1) bytes when the MSB nibble is $F.
+    /// The latter can describe sizes that don't fit in memory
+    fn rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+        match msb {
+            0x0F => 1usize
+                .checked_shl((lsb >> 2) as u32)
+                .and_then(|size| size.checked_mul((lsb & 0x03) as usize * 2 + 1)),
+            _ => Some(((msb as usize) << 8 | lsb as usize) * page_size),
+        }
+    }
+
+    /// NES 2.0 RAM sizes are 64 << shift bytes, and 0 means there is none
+    fn shift_size(shift: u8) -> usize {
+        match shift & 0x0F {
+            0 => 0,
+            shift => 64 << shift,
+        }
     }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Timing {
+    NTSC,
+    PAL,
+    /// Works on both NTSC and PAL consoles
+    MultipleRegion,
+    Dendy,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConsoleType {
+    NES,
+    VsSystem,
+    PlayChoice10,
+    /// Extended console type from byte 13 of NES 2.0 headers
+    Extended(u8),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayersAdapter,
+    VsSystem4016,
+    VsSystem4017,
+    VsZapper,
+    Zapper,
+    TwoZappers,
+    BandaiHyperShot,
+    PowerPadSideA,
+    PowerPadSideB,
+    FamilyTrainerSideA,
+    FamilyTrainerSideB,
+    ArkanoidVausNES,
+    ArkanoidVausFamicom,
+    FamilyBasicKeyboard,
+    SNESMouse,
+    Other(u8),
+}
+
+impl From<u8> for ExpansionDevice {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x02 => ExpansionDevice::FourScore,
+            0x03 => ExpansionDevice::FamicomFourPlayersAdapter,
+            0x04 => ExpansionDevice::VsSystem4016,
+            0x05 => ExpansionDevice::VsSystem4017,
+            0x07 => ExpansionDevice::VsZapper,
+            0x08 => ExpansionDevice::Zapper,
+            0x09 => ExpansionDevice::TwoZappers,
+            0x0A => ExpansionDevice::BandaiHyperShot,
+            0x0B => ExpansionDevice::PowerPadSideA,
+            0x0C => ExpansionDevice::PowerPadSideB,
+            0x0D => ExpansionDevice::FamilyTrainerSideA,
+            0x0E => ExpansionDevice::FamilyTrainerSideB,
+            0x0F => ExpansionDevice::ArkanoidVausNES,
+            0x10 => ExpansionDevice::ArkanoidVausFamicom,
+            0x23 => ExpansionDevice::FamilyBasicKeyboard,
+            0x29 => ExpansionDevice::SNESMouse,
+            value => ExpansionDevice::Other(value),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Mirroring {
+    #[default]
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
 
-    fn chr_rom_size(&self) -> usize {
-        self.chr_rom_page_count as usize * CRH_PAGE_SIZE
+impl Mirroring {
+    /// Maps a logical name table (0-3 for $2000, $2400, $2800 and $2C00) to the physical 1 KiB page backing it
+    pub fn name_table_index(&self, name_table: usize) -> usize {
+        match self {
+            Mirroring::Horizontal => name_table / 2,
+            Mirroring::Vertical => name_table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => name_table,
+        }
     }
 }
 
-// pub enum Mirroring {
-//     Horizontal,
-//     Vertical,
-// }
+#[cfg(test)]
+pub mod tests {
+    use super::{
+        checksum, ConsoleType, ExpansionDevice, Game, Mirroring, ROMError, Timing, CRH_PAGE_SIZE, PRG_PAGE_SIZE,
+        PRG_RAM_PAGE_SIZE, ROM, TRAINER_SIZE,
+    };
+
+    fn rom(header: [u8; 16], content_size: usize) -> ROM {
+        let mut content = header.to_vec();
+        content.resize(0x10 + content_size, 0x00);
+        ROM::with_content(content).unwrap()
+    }
+
+    #[test]
+    pub fn test_ines_header() {
+        let rom = rom(
+            [
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x43, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            2 * PRG_PAGE_SIZE + CRH_PAGE_SIZE,
+        );
+        assert!(!rom.is_nes_2_0());
+        assert_eq!(rom.mapper_number(), 0x14);
+        assert!(rom.has_battery());
+        assert_eq!(rom.prg_rom().len(), 2 * PRG_PAGE_SIZE);
+        assert_eq!(rom.chr_rom().len(), CRH_PAGE_SIZE);
+        assert_eq!(rom.prg_ram_size(), PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.chr_ram_size(), 0);
+        assert_eq!(rom.timing(), Timing::NTSC);
+
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x44, 0x69, 0x73, 0x6B,
+        ];
+        assert_eq!(ROM::with_content(header.to_vec()).unwrap_err(), ROMError::DirtyHeader);
+    }
+
+    #[test]
+    pub fn test_nes_2_0_header() {
+        let rom = rom(
+            [
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x42, 0x0B, 0x41, 0x01, 0x70, 0x07, 0x01, 0x03, 0x00, 0x08,
+            ],
+            0x102 * PRG_PAGE_SIZE,
+        );
+        assert!(rom.is_nes_2_0());
+        assert_eq!(rom.mapper_number(), 0x104);
+        assert_eq!(rom.submapper_number(), 4);
+        assert_eq!(rom.prg_rom().len(), 0x102 * PRG_PAGE_SIZE);
+        assert_eq!(rom.chr_rom().len(), 0);
+        assert_eq!(rom.prg_ram_size(), 0);
+        assert_eq!(rom.prg_nvram_size(), 8 * 1024);
+        assert_eq!(rom.chr_ram_size(), 8 * 1024);
+        assert_eq!(rom.chr_nvram_size(), 0);
+        assert_eq!(rom.timing(), Timing::PAL);
+        assert_eq!(rom.console_type(), ConsoleType::Extended(3));
+        assert_eq!(rom.default_expansion_device(), ExpansionDevice::Zapper);
+    }
+
+    #[test]
+    pub fn test_nes_2_0_exponent_multiplier_size() {
+        // 2^4 * 3 = 48 bytes of PRG ROM
+        let rom = rom(
+            [
+                0x4E, 0x45, 0x53, 0x1A, 0x11, 0x00, 0x00, 0x08, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            48,
+        );
+        assert_eq!(rom.prg_rom().len(), 48);
+    }
+
+    #[test]
+    pub fn test_trainer() {
+        let mut content = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        content.extend_from_slice(&[0x01; TRAINER_SIZE]);
+        content.extend_from_slice(&[0x02; PRG_PAGE_SIZE]);
+        content.extend_from_slice(&[0x03; CRH_PAGE_SIZE]);
+        let rom = ROM::with_content(content).unwrap();
+        assert_eq!(rom.trainer(), Some(&[0x01; TRAINER_SIZE][..]));
+        assert_eq!(rom.prg_rom(), &[0x02; PRG_PAGE_SIZE][..]);
+        assert_eq!(rom.chr_rom(), &[0x03; CRH_PAGE_SIZE][..]);
+
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            ROM::with_content(header.to_vec()).unwrap_err(),
+            ROMError::TruncatedTrainer
+        );
+    }
+
+    #[test]
+    pub fn test_database_override() {
+        let mut content = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        content.extend_from_slice(&[0x01; PRG_PAGE_SIZE]);
+        content.extend_from_slice(&[0x02; CRH_PAGE_SIZE]);
+        let prg_and_chr_rom = &content[0x10..];
+        let game = Game {
+            title: "Test",
+            crc32: checksum::crc32(prg_and_chr_rom),
+            sha1: Some(checksum::sha1(prg_and_chr_rom)),
+            mapper_number: 3,
+            submapper_number: 2,
+            mirroring: Mirroring::Vertical,
+            has_battery: true,
+        };
+        let rom = ROM::with_database(content.clone(), Box::leak(Box::new([game]))).unwrap();
+        assert_eq!(rom.title(), Some("Test"));
+        assert!(rom.header_overridden());
+        assert_eq!(rom.mapper_number(), 3);
+        assert_eq!(rom.submapper_number(), 2);
+        assert_eq!(rom.mirroring(), Mirroring::Vertical);
+        assert!(rom.has_battery());
+
+        let game = Game {
+            sha1: Some([0x00; 20]),
+            ..game
+        };
+        let rom = ROM::with_database(content, Box::leak(Box::new([game]))).unwrap();
+        assert_eq!(rom.title(), None);
+        assert!(!rom.header_overridden());
+        assert_eq!(rom.mapper_number(), 0);
+        assert_eq!(rom.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    pub fn test_database() {
+        let mut content = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        content.extend_from_slice(&[0x00; 2 * PRG_PAGE_SIZE + CRH_PAGE_SIZE]);
+        let rom = ROM::with_content(content.clone()).unwrap();
+        assert_eq!(rom.title(), None);
+
+        // The last bytes make the CRC-32 collide with the one of Super Mario Bros., but not the SHA-1
+        let length = content.len();
+        content[length - 4..].copy_from_slice(&[0xC0, 0xDB, 0x28, 0xBD]);
+        assert_eq!(checksum::crc32(&content[0x10..]), 0x3337_EC46);
+        let rom = ROM::with_content(content).unwrap();
+        assert_eq!(rom.title(), None);
+        assert!(!rom.header_overridden());
+        assert_eq!(rom.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    pub fn test_rom_errors() {
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            ROM::with_content(vec![0x4E, 0x45, 0x53]).unwrap_err(),
+            ROMError::InvalidMagic
+        );
+        assert_eq!(
+            ROM::with_content(header[..8].to_vec()).unwrap_err(),
+            ROMError::TruncatedHeader
+        );
+
+        let mut content = header.to_vec();
+        content.resize(0x10 + PRG_PAGE_SIZE, 0x00);
+        assert_eq!(
+            ROM::with_content(content.clone()).unwrap_err(),
+            ROMError::TruncatedPRGROM {
+                expected: 2 * PRG_PAGE_SIZE,
+                actual: PRG_PAGE_SIZE
+            }
+        );
+
+        content.resize(0x10 + 2 * PRG_PAGE_SIZE + 0x100, 0x00);
+        assert_eq!(
+            ROM::with_content(content).unwrap_err(),
+            ROMError::TruncatedCHRROM {
+                expected: CRH_PAGE_SIZE,
+                actual: 0x100
+            }
+        );
+
+        // 2^63 * 7 bytes of CHR ROM
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x00, 0xFF, 0x00, 0x08, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            ROM::with_content(header.to_vec()).unwrap_err(),
+            ROMError::InvalidCHRROMSize
+        );
+    }
+}
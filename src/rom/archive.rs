@@ -0,0 +1,96 @@
+use crate::error::ArchiveError;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+const ROM_EXTENSIONS: &[&str] = &[".nes", ".unf", ".unif", ".fds"];
+
+/// Unpacks a ROM from a zip or gzip container, and passes any other content through as it is.
+/// `entry` picks a zip entry by name, otherwise the first one with a ROM extension is used
+pub fn extract(content: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, ArchiveError> {
+    match content.as_slice() {
+        [0x50, 0x4B, 0x03, 0x04, ..] => extract_zip(content, entry),
+        [0x1F, 0x8B, ..] => {
+            let mut buffer = Vec::new();
+            GzDecoder::new(content.as_slice())
+                .read_to_end(&mut buffer)
+                .map_err(|err| ArchiveError::Corrupted(err.to_string()))?;
+            Ok(buffer)
+        }
+        _ => Ok(content),
+    }
+}
+
+fn extract_zip(content: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, ArchiveError> {
+    let corrupted = |err: zip::result::ZipError| ArchiveError::Corrupted(err.to_string());
+    let mut archive = ZipArchive::new(Cursor::new(content)).map_err(corrupted)?;
+
+    let mut names = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        names.push(archive.by_index(index).map_err(corrupted)?.name().to_string());
+    }
+    let index = match entry {
+        Some(entry) => names
+            .iter()
+            .position(|name| name == entry)
+            .ok_or_else(|| ArchiveError::EntryNotFound(entry.to_string()))?,
+        None => names
+            .iter()
+            .position(|name| {
+                let name = name.to_lowercase();
+                ROM_EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+            })
+            .ok_or(ArchiveError::NoROM)?,
+    };
+
+    let mut buffer = Vec::new();
+    archive
+        .by_index(index)
+        .map_err(corrupted)?
+        .read_to_end(&mut buffer)
+        .map_err(|err| ArchiveError::Corrupted(err.to_string()))?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::extract;
+    use crate::error::ArchiveError;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(
+                    *name,
+                    FileOptions::default().compression_method(CompressionMethod::Deflated),
+                )
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    pub fn test_zip() {
+        let archive = zip(&[("readme.txt", b"text"), ("game.NES", b"first"), ("hack.nes", b"second")]);
+        assert_eq!(extract(archive.clone(), None), Ok(b"first".to_vec()));
+        assert_eq!(extract(archive.clone(), Some("hack.nes")), Ok(b"second".to_vec()));
+        assert_eq!(
+            extract(archive, Some("missing.nes")),
+            Err(ArchiveError::EntryNotFound("missing.nes".to_string()))
+        );
+        assert_eq!(extract(zip(&[("readme.txt", b"text")]), None), Err(ArchiveError::NoROM));
+    }
+
+    #[test]
+    pub fn test_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"NES\x1A").unwrap();
+        assert_eq!(extract(encoder.finish().unwrap(), None), Ok(b"NES\x1A".to_vec()));
+        assert_eq!(extract(b"NES\x1A".to_vec(), None), Ok(b"NES\x1A".to_vec()));
+    }
+}
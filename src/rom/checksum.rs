@@ -0,0 +1,86 @@
+/// CRC-32 with the IEEE 802.3 polynomial, as used by No-Intro and NesCartDB
+pub fn crc32(data: &[u8]) -> u32 {
+    const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+    !data.iter().fold(0xFFFF_FFFF, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 0x01 {
+            0 => crc >> 1,
+            _ => (crc >> 1) ^ POLYNOMIAL,
+        })
+    })
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    // The message is padded with a 1 bit, zeros and its length in bits to a multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0x00);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *value = value.wrapping_add(*add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{crc32, sha1};
+
+    #[test]
+    pub fn test_crc32() {
+        assert_eq!(crc32(b""), 0x0000_0000);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    pub fn test_sha1() {
+        assert_eq!(
+            sha1(b"abc"),
+            [
+                0xA9, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E, 0x25, 0x71, 0x78, 0x50, 0xC2, 0x6C, 0x9C,
+                0xD0, 0xD8, 0x9D
+            ]
+        );
+        assert_eq!(sha1(&[0x61; 1000])[..4], [0x29, 0x1E, 0x9A, 0x6C]);
+    }
+}
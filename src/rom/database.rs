@@ -0,0 +1,111 @@
+use super::Mirroring;
+use std::sync::OnceLock;
+
+/// Known-good board info of a dump, identified by the checksums of its PRG and CHR ROM without the header
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Game {
+    pub title: &'static str,
+    pub crc32: u32,
+    /// Guards against CRC-32 collisions when known
+    pub sha1: Option<[u8; 20]>,
+    pub mapper_number: u16,
+    pub submapper_number: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+}
+
+/// Export of the game database, parsed the first time a ROM is loaded
+const EXPORT: &str = include_str!("games.csv");
+
+pub fn games() -> &'static [Game] {
+    static GAMES: OnceLock<Vec<Game>> = OnceLock::new();
+    GAMES.get_or_init(|| parse(EXPORT))
+}
+
+pub fn find(games: &'static [Game], crc32: u32, sha1: &[u8; 20]) -> Option<&'static Game> {
+    games
+        .iter()
+        .find(|game| game.crc32 == crc32 && game.sha1.is_none_or(|game_sha1| game_sha1 == *sha1))
+}
+
+/// Skips the comment lines starting with #, and the lines that aren't in the format described at the top of the export
+fn parse(export: &'static str) -> Vec<Game> {
+    export
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &'static str) -> Option<Game> {
+    // Titles may contain commas, so they take the rest of the line
+    let mut fields = line.splitn(7, ',');
+    let crc32 = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let sha1 = match fields.next()? {
+        "" => None,
+        sha1 => Some(parse_sha1(sha1)?),
+    };
+    let mapper_number = fields.next()?.parse().ok()?;
+    let submapper_number = fields.next()?.parse().ok()?;
+    let mirroring = match fields.next()? {
+        "H" => Mirroring::Horizontal,
+        "V" => Mirroring::Vertical,
+        "4" => Mirroring::FourScreen,
+        _ => return None,
+    };
+    let has_battery = match fields.next()? {
+        "0" => false,
+        "1" => true,
+        _ => return None,
+    };
+    Some(Game {
+        title: fields.next()?,
+        crc32,
+        sha1,
+        mapper_number,
+        submapper_number,
+        mirroring,
+        has_battery,
+    })
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut sha1 = [0u8; 20];
+    for (index, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(sha1)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{parse, parse_line, EXPORT};
+    use crate::rom::Mirroring;
+
+    #[test]
+    pub fn test_export() {
+        let line_count = EXPORT
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .count();
+        assert_eq!(parse(EXPORT).len(), line_count);
+
+        let game = parse_line("0123ABCD,,4,1,4,1,Title, With Comma").unwrap();
+        assert_eq!(game.crc32, 0x0123_ABCD);
+        assert_eq!(game.sha1, None);
+        assert_eq!(game.mapper_number, 4);
+        assert_eq!(game.submapper_number, 1);
+        assert_eq!(game.mirroring, Mirroring::FourScreen);
+        assert!(game.has_battery);
+        assert_eq!(game.title, "Title, With Comma");
+
+        let game = parse_line("0123ABCD,000102030405060708090A0B0C0D0E0F10111213,0,0,H,0,Title").unwrap();
+        assert_eq!(game.sha1.unwrap()[0x13], 0x13);
+        assert_eq!(parse_line("0123ABCD,0001,0,0,H,0,Title"), None);
+        assert_eq!(parse_line("0123ABCD,,0,0,X,0,Title"), None);
+        assert_eq!(parse_line("0123ABCD,,0,0,H,0"), None);
+    }
+}
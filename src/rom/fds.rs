@@ -0,0 +1,61 @@
+use crate::error::ROMError;
+
+pub(crate) const DISK_SIDE_SIZE: usize = 65500;
+const FWNES_HEADER_SIZE: usize = 0x10;
+const FWNES_MAGIC: &[u8] = b"FDS\x1A";
+const DISK_INFO_MAGIC: &[u8] = b"\x01*NINTENDO-HVC*";
+
+/// Famicom Disk System image, made of 65500 byte disk sides without the gaps and CRCs of the physical disk
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Disk {
+    sides: Vec<Vec<u8>>,
+}
+
+impl Disk {
+    /// Accepts images with or without the 16 byte fwNES header
+    pub fn with_content(content: &[u8]) -> Result<Self, ROMError> {
+        let sides = match content {
+            [0x46, 0x44, 0x53, 0x1A, ..] => content.get(FWNES_HEADER_SIZE..).ok_or(ROMError::TruncatedHeader)?,
+            _ => content,
+        };
+        if sides.is_empty() || sides.len() % DISK_SIDE_SIZE != 0 {
+            return Err(ROMError::InvalidDiskImage);
+        }
+        Ok(Self {
+            sides: sides.chunks_exact(DISK_SIDE_SIZE).map(<[u8]>::to_vec).collect(),
+        })
+    }
+
+    pub fn is_disk_image(content: &[u8]) -> bool {
+        content.starts_with(FWNES_MAGIC) || content.starts_with(DISK_INFO_MAGIC)
+    }
+
+    pub fn sides(&self) -> &[Vec<u8>] {
+        &self.sides
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Disk, DISK_INFO_MAGIC, DISK_SIDE_SIZE};
+    use crate::error::ROMError;
+
+    #[test]
+    pub fn test_disk() {
+        let mut side = DISK_INFO_MAGIC.to_vec();
+        side.resize(DISK_SIDE_SIZE, 0x00);
+        let content = [side.clone(), side.clone()].concat();
+        assert!(Disk::is_disk_image(&content));
+        assert_eq!(Disk::with_content(&content).unwrap().sides().len(), 2);
+
+        let mut header = b"FDS\x1A\x02".to_vec();
+        header.resize(0x10, 0x00);
+        let content = [header, content].concat();
+        assert!(Disk::is_disk_image(&content));
+        let disk = Disk::with_content(&content).unwrap();
+        assert_eq!(disk.sides(), &[side.clone(), side.clone()]);
+
+        assert_eq!(Disk::with_content(&side[..0x1000]), Err(ROMError::InvalidDiskImage));
+        assert!(!Disk::is_disk_image(b"NES\x1A"));
+    }
+}
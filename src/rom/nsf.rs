@@ -0,0 +1,293 @@
+use super::Timing;
+use crate::error::ROMError;
+
+const NSF_MAGIC: &[u8] = b"NESM\x1A";
+const NSFE_MAGIC: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+const CHUNK_HEADER_SIZE: usize = 8;
+/// Play rates in microseconds used when the file doesn't specify one, about 60.1 Hz and 50 Hz
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+/// NES Sound Format file, which holds the music code and data of a game together with the routines that play it.
+/// Both the classic NSF header and the chunked NSFe format are read into the same structure
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NSF {
+    song_count: u8,
+    starting_song: u8,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    title: String,
+    artist: String,
+    copyright: String,
+    ntsc_speed: u16,
+    pal_speed: u16,
+    bank_switching: Option<[u8; 8]>,
+    timing: Timing,
+    extra_sound_chips: u8,
+    data: Vec<u8>,
+}
+
+impl NSF {
+    pub fn with_content(content: &[u8]) -> Result<Self, ROMError> {
+        if content.starts_with(NSF_MAGIC) {
+            Self::with_nsf_content(content)
+        } else if content.starts_with(NSFE_MAGIC) {
+            Self::with_nsfe_content(content)
+        } else {
+            Err(ROMError::InvalidMagic)
+        }
+    }
+
+    pub fn is_nsf(content: &[u8]) -> bool {
+        content.starts_with(NSF_MAGIC) || content.starts_with(NSFE_MAGIC)
+    }
+
+    fn with_nsf_content(content: &[u8]) -> Result<Self, ROMError> {
+        let header = content.get(..NSF_HEADER_SIZE).ok_or(ROMError::TruncatedHeader)?;
+        let word = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let mut banks = [0; 8];
+        banks.copy_from_slice(&header[0x70..0x78]);
+        Ok(Self {
+            song_count: header[0x06],
+            // Songs are numbered from 1 in the header
+            starting_song: header[0x07].saturating_sub(1),
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            title: string(&header[0x0E..0x2E]),
+            artist: string(&header[0x2E..0x4E]),
+            copyright: string(&header[0x4E..0x6E]),
+            ntsc_speed: non_zero_or(word(0x6E), DEFAULT_NTSC_SPEED),
+            pal_speed: non_zero_or(word(0x78), DEFAULT_PAL_SPEED),
+            bank_switching: banks.iter().any(|bank| *bank != 0).then_some(banks),
+            timing: timing(header[0x7A]),
+            extra_sound_chips: header[0x7B],
+            data: content[NSF_HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    fn with_nsfe_content(content: &[u8]) -> Result<Self, ROMError> {
+        let mut chunks = &content[NSFE_MAGIC.len()..];
+        let mut info = None;
+        let mut data = None;
+        let mut banks = None;
+        let mut rate = None;
+        let mut auth = None;
+        while !chunks.is_empty() {
+            let (id, chunk) = match chunks {
+                [l0, l1, l2, l3, a, b, c, d, ..] => {
+                    let length = u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize;
+                    let id = [*a, *b, *c, *d];
+                    let chunk = chunks[CHUNK_HEADER_SIZE..]
+                        .get(..length)
+                        .ok_or_else(|| ROMError::TruncatedChunk(String::from_utf8_lossy(&id).into_owned()))?;
+                    chunks = &chunks[CHUNK_HEADER_SIZE + length..];
+                    (id, chunk)
+                }
+                _ => {
+                    let id = chunks.get(4..).unwrap_or_default();
+                    return Err(ROMError::TruncatedChunk(String::from_utf8_lossy(id).into_owned()));
+                }
+            };
+            match &id {
+                b"INFO" => info = Some(chunk),
+                b"DATA" => data = Some(chunk),
+                b"BANK" => banks = Some(chunk),
+                b"RATE" => rate = Some(chunk),
+                b"auth" => auth = Some(chunk),
+                b"NEND" => break,
+                // Chunks starting with an uppercase letter must be understood to play the file
+                [b'A'..=b'Z', ..] => return Err(ROMError::InvalidChunk(String::from_utf8_lossy(&id).into_owned())),
+                _ => (),
+            }
+        }
+
+        let info = info.ok_or(ROMError::MissingChunk("INFO"))?;
+        if info.len() < 8 {
+            return Err(ROMError::TruncatedChunk("INFO".to_string()));
+        }
+        // Missing fields at the end of a chunk read as 0
+        let word = |chunk: &[u8], offset: usize| match chunk.get(offset..offset + 2) {
+            Some([low, high]) => u16::from_le_bytes([*low, *high]),
+            _ => 0,
+        };
+        let mut authors = auth.unwrap_or_default().split(|byte| *byte == 0).map(string);
+        let bank_switching = banks.map(|banks| {
+            let mut bank_switching = [0; 8];
+            let length = banks.len().min(8);
+            bank_switching[..length].copy_from_slice(&banks[..length]);
+            bank_switching
+        });
+        Ok(Self {
+            song_count: info.get(8).copied().unwrap_or(1),
+            // NSFe numbers songs from 0
+            starting_song: info.get(9).copied().unwrap_or(0),
+            load_address: word(info, 0),
+            init_address: word(info, 2),
+            play_address: word(info, 4),
+            title: authors.next().unwrap_or_default(),
+            artist: authors.next().unwrap_or_default(),
+            copyright: authors.next().unwrap_or_default(),
+            ntsc_speed: non_zero_or(word(rate.unwrap_or_default(), 0), DEFAULT_NTSC_SPEED),
+            pal_speed: non_zero_or(word(rate.unwrap_or_default(), 2), DEFAULT_PAL_SPEED),
+            bank_switching,
+            timing: timing(info[6]),
+            extra_sound_chips: info[7],
+            data: data.ok_or(ROMError::MissingChunk("DATA"))?.to_vec(),
+        })
+    }
+
+    pub fn song_count(&self) -> u8 {
+        self.song_count
+    }
+
+    /// Index of the song to play first, counted from 0
+    pub fn starting_song(&self) -> u8 {
+        self.starting_song
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    pub fn init_address(&self) -> u16 {
+        self.init_address
+    }
+
+    pub fn play_address(&self) -> u16 {
+        self.play_address
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn artist(&self) -> &str {
+        &self.artist
+    }
+
+    pub fn copyright(&self) -> &str {
+        &self.copyright
+    }
+
+    /// Microseconds between two calls of the PLAY routine on an NTSC console
+    pub fn ntsc_speed(&self) -> u16 {
+        self.ntsc_speed
+    }
+
+    /// Microseconds between two calls of the PLAY routine on a PAL console
+    pub fn pal_speed(&self) -> u16 {
+        self.pal_speed
+    }
+
+    /// Initial 4 KiB banks of $8000-$FFFF, none when the music isn't bank switched
+    pub fn bank_switching(&self) -> Option<[u8; 8]> {
+        self.bank_switching
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Bit field of the expansion audio chips the music uses, like the VRC6 or the FDS
+    pub fn extra_sound_chips(&self) -> u8 {
+        self.extra_sound_chips
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+fn timing(flags: u8) -> Timing {
+    match flags & 0x03 {
+        0x00 => Timing::NTSC,
+        0x01 => Timing::PAL,
+        _ => Timing::MultipleRegion,
+    }
+}
+
+fn non_zero_or(value: u16, default: u16) -> u16 {
+    match value {
+        0 => default,
+        value => value,
+    }
+}
+
+/// Text fields are zero terminated, or fill their whole space
+fn string(bytes: &[u8]) -> String {
+    let text = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(text).into_owned()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{NSF, NSF_HEADER_SIZE};
+    use crate::{error::ROMError, rom::Timing};
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u32).to_le_bytes(), id, data].concat()
+    }
+
+    #[test]
+    pub fn test_nsf() {
+        let mut content = b"NESM\x1A\x01\x05\x02\x00\x80\x03\x80\x06\x80Song".to_vec();
+        content.resize(0x6E, 0x00);
+        content.extend(&[0x1A, 0x41]);
+        content.extend(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        content.extend(&[0x00, 0x00, 0x02, 0x00]);
+        content.resize(NSF_HEADER_SIZE, 0x00);
+        content.extend(&[0x60; 4]);
+        assert!(NSF::is_nsf(&content));
+
+        let nsf = NSF::with_content(&content).unwrap();
+        assert_eq!(nsf.song_count(), 5);
+        assert_eq!(nsf.starting_song(), 1);
+        assert_eq!(nsf.load_address(), 0x8000);
+        assert_eq!(nsf.init_address(), 0x8003);
+        assert_eq!(nsf.play_address(), 0x8006);
+        assert_eq!(nsf.title(), "Song");
+        assert_eq!(nsf.ntsc_speed(), 0x411A);
+        assert_eq!(nsf.pal_speed(), 19997);
+        assert_eq!(nsf.bank_switching(), Some([0, 0, 0, 0, 0, 0, 0, 1]));
+        assert_eq!(nsf.timing(), Timing::MultipleRegion);
+        assert_eq!(nsf.data(), &[0x60; 4]);
+
+        assert_eq!(NSF::with_content(&content[..0x40]), Err(ROMError::TruncatedHeader));
+    }
+
+    #[test]
+    pub fn test_nsfe() {
+        let content = [
+            b"NSFE".to_vec(),
+            chunk(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x01, 0x00, 0x03, 0x02]),
+            chunk(b"auth", b"Song\0Artist\0"),
+            chunk(b"tlbl", b"First\0Second\0Third\0"),
+            chunk(b"DATA", &[0x60; 4]),
+            chunk(b"NEND", &[]),
+        ]
+        .concat();
+        assert!(NSF::is_nsf(&content));
+
+        let nsf = NSF::with_content(&content).unwrap();
+        assert_eq!(nsf.song_count(), 3);
+        assert_eq!(nsf.starting_song(), 2);
+        assert_eq!(nsf.play_address(), 0x8006);
+        assert_eq!(nsf.title(), "Song");
+        assert_eq!(nsf.artist(), "Artist");
+        assert_eq!(nsf.ntsc_speed(), 16639);
+        assert_eq!(nsf.bank_switching(), None);
+        assert_eq!(nsf.timing(), Timing::PAL);
+        assert_eq!(nsf.data(), &[0x60; 4]);
+
+        let content = [b"NSFE".to_vec(), chunk(b"DATA", &[0x60; 4])].concat();
+        assert_eq!(NSF::with_content(&content), Err(ROMError::MissingChunk("INFO")));
+        let content = [b"NSFE".to_vec(), chunk(b"VRC7", &[])].concat();
+        assert_eq!(
+            NSF::with_content(&content),
+            Err(ROMError::InvalidChunk("VRC7".to_string()))
+        );
+    }
+}
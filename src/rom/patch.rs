@@ -0,0 +1,403 @@
+use super::checksum::crc32;
+use crate::error::PatchError;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// UPS and BPS patches end with the CRC-32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+/// Target sizes come straight from the patch, so they are capped before any memory is allocated for them
+const MAX_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+/// Applies an IPS, UPS or BPS patch to the content of a ROM file, telling the format apart by its magic
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(source, &patch[IPS_MAGIC.len()..])
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(source, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(source, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+fn apply_ips(source: &[u8], records: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = source.to_vec();
+    let mut reader = Reader::new(records);
+    loop {
+        if reader.remaining().starts_with(IPS_EOF) {
+            reader.bytes(IPS_EOF.len())?;
+            break;
+        }
+        let offset = reader.u24()?;
+        let (length, data) = match reader.u16()? {
+            // Run-length encoded record
+            0 => {
+                let length = reader.u16()?;
+                (length, vec![reader.u8()?; length])
+            }
+            length => (length, reader.bytes(length)?.to_vec()),
+        };
+        if target.len() < offset + length {
+            target.resize(offset + length, 0x00);
+        }
+        target[offset..offset + length].copy_from_slice(&data);
+    }
+
+    // An extension of the format truncates the target to the size after the EOF marker
+    if let Ok(size) = reader.u24() {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, target_crc32) = verify(source, patch)?;
+    let mut reader = Reader::new(&body[UPS_MAGIC.len()..]);
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    if source.len() != source_size {
+        return Err(PatchError::SourceSizeMismatch {
+            expected: source_size,
+            actual: source.len(),
+        });
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::TargetTooLarge(target_size));
+    }
+
+    // The patch XORs the differing bytes, with a zero byte ending each run
+    let mut target = source.to_vec();
+    target.resize(target_size, 0x00);
+    let mut offset = 0;
+    while !reader.remaining().is_empty() {
+        offset += reader.number()?;
+        loop {
+            let value = reader.u8()?;
+            if let Some(byte) = target.get_mut(offset) {
+                *byte ^= value;
+            }
+            offset += 1;
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&target, target_crc32)?;
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, target_crc32) = verify(source, patch)?;
+    let mut reader = Reader::new(&body[BPS_MAGIC.len()..]);
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source.len() != source_size {
+        return Err(PatchError::SourceSizeMismatch {
+            expected: source_size,
+            actual: source.len(),
+        });
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::TargetTooLarge(target_size));
+    }
+
+    // The target grows with the actions, so it never takes more memory than the patch actually writes
+    let mut target = Vec::new();
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while !reader.remaining().is_empty() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        match action & 0x03 {
+            // SourceRead
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(source.get(start..start + length).ok_or(PatchError::OutOfBounds)?);
+            }
+            // TargetRead
+            1 => target.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy
+            2 => {
+                source_offset = relative_offset(source_offset, reader.number()?)?;
+                let data = source
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(data);
+                source_offset += length;
+            }
+            // TargetCopy, which may overlap the bytes it writes to repeat a pattern
+            _ => {
+                target_offset = relative_offset(target_offset, reader.number()?)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+        if target.len() > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+    }
+    if target.len() != target_size {
+        return Err(PatchError::TargetSizeMismatch {
+            expected: target_size,
+            actual: target.len(),
+        });
+    }
+
+    check_target(&target, target_crc32)?;
+    Ok(target)
+}
+
+/// Checks the footer of a UPS or BPS patch against the patch and the source, and returns the body of the patch with
+/// the expected target checksum
+fn verify<'a>(source: &[u8], patch: &'a [u8]) -> Result<(&'a [u8], u32), PatchError> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+    let checksum =
+        |index: usize| u32::from_le_bytes([footer[index], footer[index + 1], footer[index + 2], footer[index + 3]]);
+    let (source_crc32, target_crc32, patch_crc32) = (checksum(0), checksum(4), checksum(8));
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc32 {
+        return Err(PatchError::PatchChecksumMismatch {
+            expected: patch_crc32,
+            actual,
+        });
+    }
+    let actual = crc32(source);
+    if actual != source_crc32 {
+        return Err(PatchError::SourceChecksumMismatch {
+            expected: source_crc32,
+            actual,
+        });
+    }
+    Ok((body, target_crc32))
+}
+
+fn check_target(target: &[u8], target_crc32: u32) -> Result<(), PatchError> {
+    match crc32(target) {
+        actual if actual != target_crc32 => Err(PatchError::TargetChecksumMismatch {
+            expected: target_crc32,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// BPS copy offsets are relative to the previous copy, with the lowest bit as the sign
+fn relative_offset(offset: usize, data: usize) -> Result<usize, PatchError> {
+    match data & 0x01 {
+        0 => offset.checked_add(data >> 1),
+        _ => offset.checked_sub(data >> 1),
+    }
+    .ok_or(PatchError::OutOfBounds)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        if self.data.len() < length {
+            return Err(PatchError::Truncated);
+        }
+        let (bytes, data) = self.data.split_at(length);
+        self.data = data;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, PatchError> {
+        self.bytes(2).map(|bytes| (bytes[0] as usize) << 8 | bytes[1] as usize)
+    }
+
+    fn u24(&mut self) -> Result<usize, PatchError> {
+        self.bytes(3)
+            .map(|bytes| (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize)
+    }
+
+    /// Variable length number of UPS and BPS. Every byte holds 7 bits, and the highest bit marks the last one
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut number = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.u8()?;
+            number = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            number = number.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{apply, crc32, BPS_MAGIC, MAX_TARGET_SIZE, UPS_MAGIC};
+    use crate::error::PatchError;
+
+    fn encode_number(mut number: usize, patch: &mut Vec<u8>) {
+        loop {
+            let byte = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                patch.push(byte | 0x80);
+                return;
+            }
+            patch.push(byte);
+            number -= 1;
+        }
+    }
+
+    fn append_footer(source: &[u8], target: &[u8], mut patch: Vec<u8>) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    pub fn test_ips() {
+        let source = [0x00; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // Run-length encoded record growing the target
+        patch.extend_from_slice(&[0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply(&source, &patch),
+            Ok(vec![0x00, 0xAA, 0xBB, 0x00, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC])
+        );
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply(&source, &patch), Ok(vec![0x00, 0xAA, 0xBB, 0x00]));
+
+        assert_eq!(apply(&source, b"PATCH\x00\x00"), Err(PatchError::Truncated));
+        assert_eq!(apply(&source, b"PACTH"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    pub fn test_ups() {
+        let source = [0x01, 0x02, 0x03, 0x04];
+        let target = [0x01, 0x12, 0x03, 0x04, 0x05];
+        let mut patch = UPS_MAGIC.to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(target.len(), &mut patch);
+        encode_number(1, &mut patch);
+        patch.extend_from_slice(&[0x10, 0x00]);
+        encode_number(1, &mut patch);
+        patch.extend_from_slice(&[0x05, 0x00]);
+        let patch = append_footer(&source, &target, patch);
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+
+        assert_eq!(
+            apply(&[0x01, 0x02, 0x03, 0x05], &patch),
+            Err(PatchError::SourceChecksumMismatch {
+                expected: crc32(&source),
+                actual: crc32(&[0x01, 0x02, 0x03, 0x05])
+            })
+        );
+
+        let mut corrupted = patch.clone();
+        corrupted[6] ^= 0xFF;
+        assert!(matches!(
+            apply(&source, &corrupted),
+            Err(PatchError::PatchChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    pub fn test_target_too_large() {
+        let source = [0x01, 0x02, 0x03, 0x04];
+        for magic in [UPS_MAGIC, BPS_MAGIC].iter() {
+            let mut patch = magic.to_vec();
+            encode_number(source.len(), &mut patch);
+            encode_number(4 * 1024 * 1024 * 1024, &mut patch);
+            encode_number(0, &mut patch);
+            let patch = append_footer(&source, &source, patch);
+            assert_eq!(
+                apply(&source, &patch),
+                Err(PatchError::TargetTooLarge(4 * 1024 * 1024 * 1024))
+            );
+        }
+
+        // A BPS patch that writes fewer bytes than it announced
+        let mut patch = BPS_MAGIC.to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(MAX_TARGET_SIZE, &mut patch);
+        encode_number(0, &mut patch);
+        encode_number((4 - 1) << 2, &mut patch);
+        let patch = append_footer(&source, &source, patch);
+        assert_eq!(
+            apply(&source, &patch),
+            Err(PatchError::TargetSizeMismatch {
+                expected: MAX_TARGET_SIZE,
+                actual: 4
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_bps() {
+        let source = [0x01, 0x02, 0x03, 0x04];
+        let target = [0x01, 0x02, 0xAA, 0xAA, 0xAA, 0x03, 0x04, 0x01];
+        let mut patch = BPS_MAGIC.to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(target.len(), &mut patch);
+        encode_number(0, &mut patch);
+        // SourceRead of 2 bytes
+        encode_number((2 - 1) << 2, &mut patch);
+        // TargetRead of 1 byte
+        encode_number(1, &mut patch);
+        patch.push(0xAA);
+        // TargetCopy of 2 bytes from offset 2, repeating the byte written just before
+        encode_number((2 - 1) << 2 | 3, &mut patch);
+        encode_number(2 << 1, &mut patch);
+        // SourceCopy of 2 bytes from offset 2, then of 1 byte from offset 0
+        encode_number((2 - 1) << 2 | 2, &mut patch);
+        encode_number(2 << 1, &mut patch);
+        encode_number(2, &mut patch);
+        encode_number(4 << 1 | 1, &mut patch);
+        let patch = append_footer(&source, &target, patch);
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+
+        let wrong_target = [0x00; 8];
+        let mut patch = patch[..patch.len() - 12].to_vec();
+        patch.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch.extend_from_slice(&crc32(&wrong_target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        assert_eq!(
+            apply(&source, &patch),
+            Err(PatchError::TargetChecksumMismatch {
+                expected: crc32(&wrong_target),
+                actual: crc32(&target)
+            })
+        );
+    }
+}
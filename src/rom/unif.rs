@@ -0,0 +1,200 @@
+use super::{checksum::crc32, Mirroring};
+use crate::error::ROMError;
+
+const UNIF_MAGIC: &[u8] = b"UNIF";
+const HEADER_SIZE: usize = 0x20;
+const CHUNK_HEADER_SIZE: usize = 8;
+const HEX_DIGITS: &[u8] = b"0123456789ABCDEF";
+
+/// Cartridge in the chunked UNIF format, which names the board instead of numbering the mapper
+#[derive(Debug, Default)]
+pub struct UNIF {
+    board: String,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Option<Mirroring>,
+    has_battery: bool,
+}
+
+impl UNIF {
+    pub fn with_content(content: &[u8]) -> Result<Self, ROMError> {
+        if !Self::is_unif(content) {
+            return Err(ROMError::InvalidMagic);
+        }
+        let mut chunks = content.get(HEADER_SIZE..).ok_or(ROMError::TruncatedHeader)?;
+        let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut checksums = Vec::new();
+        let mut unif = Self::default();
+        while !chunks.is_empty() {
+            let (id, data) = match chunks {
+                [a, b, c, d, l0, l1, l2, l3, ..] => {
+                    let length = u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize;
+                    let id = [*a, *b, *c, *d];
+                    let data = chunks[CHUNK_HEADER_SIZE..]
+                        .get(..length)
+                        .ok_or_else(|| ROMError::TruncatedChunk(chunk_name(&id)))?;
+                    chunks = &chunks[CHUNK_HEADER_SIZE + length..];
+                    (id, data)
+                }
+                _ => {
+                    return Err(ROMError::TruncatedChunk(
+                        String::from_utf8_lossy(&chunks[..chunks.len().min(4)]).into_owned(),
+                    ))
+                }
+            };
+            match &id {
+                b"MAPR" => {
+                    let name = data.split(|byte| *byte == 0).next().unwrap_or_default();
+                    unif.board = String::from_utf8_lossy(name).into_owned();
+                }
+                [b'P', b'R', b'G', index] => prg_chunks[chunk_index(*index, &id)?] = data,
+                [b'C', b'H', b'R', index] => chr_chunks[chunk_index(*index, &id)?] = data,
+                [b'P', b'C', b'K', _] | [b'C', b'C', b'K', _] => checksums.push((id, data)),
+                b"MIRR" => {
+                    unif.mirroring = match data.first() {
+                        Some(0) => Some(Mirroring::Horizontal),
+                        Some(1) => Some(Mirroring::Vertical),
+                        Some(2) => Some(Mirroring::SingleScreenLower),
+                        Some(3) => Some(Mirroring::SingleScreenUpper),
+                        Some(4) => Some(Mirroring::FourScreen),
+                        // Controlled by the mapper
+                        _ => None,
+                    }
+                }
+                b"BATR" => unif.has_battery = data.first().is_some_and(|battery| *battery != 0),
+                // Names, dumper info and other chunks don't affect emulation
+                _ => (),
+            }
+        }
+
+        // PCKn and CCKn hold the CRC-32 of PRGn and CHRn
+        for (id, checksum) in checksums {
+            let index = chunk_index(id[3], &id)?;
+            let data = match id[0] {
+                b'P' => prg_chunks[index],
+                _ => chr_chunks[index],
+            };
+            let expected = match checksum {
+                [b0, b1, b2, b3, ..] => u32::from_le_bytes([*b0, *b1, *b2, *b3]),
+                _ => return Err(ROMError::TruncatedChunk(chunk_name(&id))),
+            };
+            if crc32(data) != expected {
+                return Err(ROMError::ChunkChecksumMismatch(chunk_name(&id)));
+            }
+        }
+
+        if unif.board.is_empty() {
+            return Err(ROMError::MissingChunk("MAPR"));
+        }
+        unif.prg_rom = prg_chunks.concat();
+        unif.chr_rom = chr_chunks.concat();
+        Ok(unif)
+    }
+
+    pub fn is_unif(content: &[u8]) -> bool {
+        content.starts_with(UNIF_MAGIC)
+    }
+
+    /// Board name without the prefix telling who made the board, like NES- or UNL-
+    pub fn board(&self) -> &str {
+        self.board.split_once('-').map_or(&self.board, |(prefix, board)| {
+            match ["NES", "HVC", "UNL", "BTL", "BMC", "IREM", "KONAMI"].contains(&prefix) {
+                true => board,
+                false => &self.board,
+            }
+        })
+    }
+
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    pub fn chr_rom(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    /// Mirroring soldered on the board, none when the mapper controls it
+    pub fn mirroring(&self) -> Option<Mirroring> {
+        self.mirroring
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+}
+
+fn chunk_index(digit: u8, id: &[u8; 4]) -> Result<usize, ROMError> {
+    HEX_DIGITS
+        .iter()
+        .position(|hex_digit| *hex_digit == digit)
+        .ok_or_else(|| ROMError::InvalidChunk(chunk_name(id)))
+}
+
+fn chunk_name(id: &[u8; 4]) -> String {
+    String::from_utf8_lossy(id).into_owned()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Mirroring, UNIF};
+    use crate::{error::ROMError, rom::checksum::crc32};
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        [id, &(data.len() as u32).to_le_bytes(), data].concat()
+    }
+
+    fn unif(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut content = b"UNIF\x07\x00\x00\x00".to_vec();
+        content.resize(0x20, 0x00);
+        content.extend(chunks.concat());
+        content
+    }
+
+    #[test]
+    pub fn test_unif() {
+        let content = unif(&[
+            chunk(b"MAPR", b"NES-UNROM\0"),
+            chunk(b"NAME", b"Test\0"),
+            chunk(b"PRG1", &[0x02; 4]),
+            chunk(b"PRG0", &[0x01; 4]),
+            chunk(b"PCK0", &crc32(&[0x01; 4]).to_le_bytes()),
+            chunk(b"CHR0", &[0x03; 2]),
+            chunk(b"MIRR", &[0x01]),
+            chunk(b"BATR", &[0x01]),
+        ]);
+        assert!(UNIF::is_unif(&content));
+        let unif = UNIF::with_content(&content).unwrap();
+        assert_eq!(unif.board(), "UNROM");
+        assert_eq!(unif.prg_rom(), &[0x01, 0x01, 0x01, 0x01, 0x02, 0x02, 0x02, 0x02]);
+        assert_eq!(unif.chr_rom(), &[0x03, 0x03]);
+        assert_eq!(unif.mirroring(), Some(Mirroring::Vertical));
+        assert!(unif.has_battery());
+    }
+
+    #[test]
+    pub fn test_unif_errors() {
+        let content = unif(&[
+            chunk(b"MAPR", b"NES-NROM\0"),
+            chunk(b"PRG0", &[0x01; 4]),
+            chunk(b"PCK0", &[0x00; 4]),
+        ]);
+        assert_eq!(
+            UNIF::with_content(&content).unwrap_err(),
+            ROMError::ChunkChecksumMismatch("PCK0".to_string())
+        );
+
+        let content = unif(&[chunk(b"PRG0", &[0x01; 4])]);
+        assert_eq!(
+            UNIF::with_content(&content).unwrap_err(),
+            ROMError::MissingChunk("MAPR")
+        );
+
+        let mut content = unif(&[chunk(b"PRG0", &[0x01; 4])]);
+        content.truncate(content.len() - 1);
+        assert_eq!(
+            UNIF::with_content(&content).unwrap_err(),
+            ROMError::TruncatedChunk("PRG0".to_string())
+        );
+    }
+}